@@ -0,0 +1,77 @@
+//! Compares `query_execute!` with the collect-then-mutate approach it
+//! replaced. Run with `cargo bench --bench query_execute`.
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use wunderkammer::prelude::*;
+
+const ENTITIES: usize = 10_000;
+const RUNS: u32 = 10_000;
+
+#[derive(ComponentSet, Default)]
+struct Components {
+    pub pos: ComponentStorage<(i32, i32)>,
+    pub vel: ComponentStorage<(i32, i32)>,
+}
+
+fn world() -> WorldStorage<Components, ()> {
+    let mut world = WorldStorage::<Components, ()>::default();
+    for i in 0..ENTITIES {
+        let entity = world.spawn();
+        insert!(world, pos, entity, (i as i32, 0));
+        if i % 2 == 0 {
+            insert!(world, vel, entity, (1, 1));
+        }
+    }
+    world
+}
+
+fn measure(name: &str, mut f: impl FnMut()) {
+    // warm up
+    for _ in 0..RUNS / 10 {
+        f();
+    }
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        f();
+    }
+    let elapsed: Duration = start.elapsed() / RUNS;
+    println!("{name:<20} {elapsed:?} per call");
+}
+
+fn main() {
+    let mut world = world();
+    println!(
+        "{} entities with `pos`, {} with `vel`, {} runs",
+        ENTITIES,
+        ENTITIES / 2,
+        RUNS
+    );
+
+    measure("query_execute!", || {
+        query_execute!(
+            world,
+            With(pos, vel),
+            |_, p: &mut (i32, i32), v: &mut (i32, i32)| {
+                p.0 += v.0;
+                p.1 += v.1;
+            }
+        );
+        black_box(&world);
+    });
+
+    measure("collect and mutate", || {
+        let entities = query!(world, With(pos, vel)).copied().collect::<Vec<_>>();
+        let f = |_: &Entity, p: &mut (i32, i32), v: &mut (i32, i32)| {
+            p.0 += v.0;
+            p.1 += v.1;
+        };
+        for e in entities.iter() {
+            f(
+                e,
+                world.cmps.pos.get_mut(e).unwrap(),
+                world.cmps.vel.get_mut(e).unwrap(),
+            );
+        }
+        black_box(&world);
+    });
+}
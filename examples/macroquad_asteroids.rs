@@ -12,22 +12,35 @@ const CYAN: Color = Color::new(0., 1., 1., 1.);
 
 #[derive(Default, ComponentSet)]
 struct Components {
+    // floats can't be hashed for the world checksum
+    #[component(no_hash)]
     asteroid: ComponentStorage<Asteroid>,
+    #[component(no_hash)]
     bullet: ComponentStorage<Bullet>,
+    #[component(no_hash)]
     pos: ComponentStorage<Vec2>,
+    #[component(no_hash)]
     rot: ComponentStorage<f32>,
     ship: ComponentStorage<()>, // marker component
+    #[component(no_hash)]
     vel: ComponentStorage<Vec2>,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Bundle)]
+#[bundle(Components)]
+struct Object {
+    pos: Vec2,
+    vel: Vec2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 struct Asteroid {
     rot_speed: f32,
     size: f32,
     sides: u8,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 struct Bullet {
     shot_at: f64,
 }
@@ -139,9 +152,7 @@ fn spawn_asteroid(pos: Vec2, vel: Vec2, sides: u8, size: f32, world: &mut World)
 }
 
 fn spawn_object(pos: Vec2, vel: Vec2, rot: Option<f32>, world: &mut World) -> Entity {
-    let entity = world.spawn();
-    insert!(world, pos, entity, pos);
-    insert!(world, vel, entity, vel);
+    let entity = world.spawn_bundle(Object { pos, vel });
     if let Some(rot) = rot {
         insert!(world, rot, entity, rot);
     }
@@ -167,10 +178,10 @@ fn handle_ship_movement(world: &mut World) -> Option<()> {
 
     // steer
     if is_key_down(KeyCode::Right) {
-        *rot += 5. * 3.14 / 180.;
+        *rot += 5_f32.to_radians();
     }
     if is_key_down(KeyCode::Left) {
-        *rot -= 5. * 3.14 / 180.;
+        *rot -= 5_f32.to_radians();
     }
 
     Some(())
@@ -191,14 +202,13 @@ fn shoot(frame_t: f64, world: &mut World) {
 
 fn handle_bullets(frame_t: f64, world: &mut World) {
     // bullet lifetime
-    let to_remove = query_iter!(world, With(bullet))
-        .filter(|(_, b)| b.shot_at + 1.5 < frame_t)
-        .map(|(e, _)| e)
-        .collect::<Vec<_>>();
-
-    for entity in to_remove {
-        world.despawn(entity);
+    let mut commands = world.commands();
+    for (entity, bullet) in query_iter!(world, With(bullet)) {
+        if bullet.shot_at + 1.5 < frame_t {
+            commands.despawn(entity);
+        }
     }
+    commands.apply(world);
 }
 
 fn handle_collisions(world: &mut World) {
@@ -206,7 +216,7 @@ fn handle_collisions(world: &mut World) {
         return;
     };
 
-    let mut to_split = Vec::new();
+    let mut commands = world.commands();
 
     for (a_entity, a_pos, asteroid) in query_iter!(world, With(pos, asteroid)) {
         // player collision
@@ -217,35 +227,42 @@ fn handle_collisions(world: &mut World) {
         // bullet collisions
         for (b_entity, b_pos, b_vel, _) in query_iter!(world, With(pos, vel, bullet)) {
             if (*a_pos - *b_pos).length() < asteroid.size {
-                // cache data to generate child asteroids
-                to_split.push((a_entity, *a_pos, *asteroid, b_entity, *b_vel));
+                commands.despawn(a_entity);
+                commands.despawn(b_entity);
+                if asteroid.sides > 3 {
+                    // split into two smaller asteroids
+                    for dir in [Vec2::new(b_vel.y, -b_vel.x), Vec2::new(-b_vel.y, b_vel.x)] {
+                        let vel = dir.normalize() * rand::gen_range(1., 3.);
+                        spawn_asteroid_deferred(*a_pos, vel, asteroid, &mut commands);
+                    }
+                }
                 break;
             }
         }
     }
 
-    // despawn and split
-    for (a_entity, a_pos, asteroid, b_entity, b_vel) in to_split {
-        world.despawn(a_entity);
-        world.despawn(b_entity);
-
-        if asteroid.sides > 3 {
-            spawn_asteroid(
-                a_pos,
-                Vec2::new(b_vel.y, -b_vel.x).normalize() * rand::gen_range(1., 3.),
-                asteroid.sides - 1,
-                0.8 * asteroid.size,
-                world,
-            );
-            spawn_asteroid(
-                a_pos,
-                Vec2::new(-b_vel.y, b_vel.x).normalize() * rand::gen_range(1., 3.),
-                asteroid.sides - 1,
-                0.8 * asteroid.size,
-                world,
-            );
-        }
-    }
+    commands.apply(world);
+}
+
+fn spawn_asteroid_deferred(
+    pos: Vec2,
+    vel: Vec2,
+    parent: &Asteroid,
+    commands: &mut Commands<Components, Resources>,
+) {
+    let entity = commands.spawn();
+    commands.insert(entity, |c| &mut c.pos, pos);
+    commands.insert(entity, |c| &mut c.vel, vel);
+    commands.insert(entity, |c| &mut c.rot, 0.);
+    commands.insert(
+        entity,
+        |c| &mut c.asteroid,
+        Asteroid {
+            rot_speed: rand::gen_range(-2., 2.),
+            sides: parent.sides - 1,
+            size: 0.8 * parent.size,
+        },
+    );
 }
 
 fn handle_kinematics(world: &mut World) {
@@ -1,22 +1,47 @@
 #![doc = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/README.md"))]
 
+// lets the derived code refer to `::wunderkammer` inside of this crate too
+extern crate self as wunderkammer;
+
 #[cfg(feature = "scheduler")]
 pub mod scheduler;
 #[cfg(feature = "storage")]
 pub mod storage;
 
+// used by the derived code, so the users don't need a direct serde dependency
+#[cfg(feature = "serialize")]
+#[doc(hidden)]
+pub use serde as __serde;
+
 pub mod prelude {
     use super::*;
     #[cfg(feature = "storage")]
-    pub use super::{insert, query, query_execute, query_iter};
+    pub use super::{insert, query, query_execute, query_execute_pair, query_iter, try_insert};
     #[cfg(feature = "storage")]
     pub use storage::{
-        components::{ComponentSet, ComponentStorage},
+        bundle::Bundle,
+        checksum::StableHasher,
+        commands::Commands,
+        components::{ComponentSet, ComponentStorage, ForkComponents, Tick},
+        diff::{StorageDiff, StoragePatch, WorldPatch},
         entity::{Entity, EntityStorage},
+        entry::Entry,
+        error::{ComponentError, WorldError},
+        map_entities::MapEntities,
+        relation::{Arity, CascadeDespawn, OnDespawn, Relation, RelationKind},
+        schema::{ComponentSchema, Schema},
         world::WorldStorage,
     };
+    #[cfg(all(feature = "storage", feature = "serialize"))]
+    pub use storage::{
+        components::JsonValue,
+        entity_data::EntityData,
+        error::{ConsoleError, EntityDataError, PrefabError},
+        prefab::{Prefab, PrefabLibrary},
+        schema::SerdeShape,
+    };
     #[cfg(feature = "storage")]
-    pub use wunderkammer_derive::ComponentSet;
+    pub use wunderkammer_derive::{Bundle, ComponentSet, MapEntities};
 
     #[cfg(feature = "scheduler")]
     pub use scheduler::{
@@ -128,7 +128,9 @@ impl<'a> SchedulerContext<'a> {
     }
 }
 
-pub struct EventHandler<T, W>(Box<dyn Fn(&mut T, &mut W, &mut SchedulerContext) -> EventResult>);
+type HandlerFn<T, W> = dyn Fn(&mut T, &mut W, &mut SchedulerContext) -> EventResult;
+
+pub struct EventHandler<T, W>(Box<HandlerFn<T, W>>);
 
 impl<T, W> EventHandler<T, W> {
     fn execute(&self, event: &mut T, world: &mut W, context: &mut SchedulerContext) -> EventResult {
@@ -315,9 +317,9 @@ mod tests {
         assert_eq!(
             scheduler
                 .queue
-                .get(0)
+                .front()
                 .unwrap()
-                .get(0)
+                .first()
                 .unwrap()
                 .1
                 .downcast_ref::<Attack>()
@@ -351,9 +353,9 @@ mod tests {
         assert_eq!(
             scheduler
                 .queue
-                .get(0)
+                .front()
                 .unwrap()
-                .get(0)
+                .first()
                 .unwrap()
                 .1
                 .downcast_ref::<Attack>()
@@ -0,0 +1,35 @@
+use super::entity::Entity;
+
+/// A group of components that can be inserted and removed at once.
+/// Usually implemented through the derive macro, that maps the struct fields
+/// to the `ComponentSet` fields of the same name:
+/// ```
+/// use wunderkammer::prelude::*;
+///
+/// #[derive(ComponentSet, Default)]
+/// struct C {
+///     pub health: ComponentStorage<u32>,
+///     pub name: ComponentStorage<String>,
+///     pub player: ComponentStorage<()>,
+/// }
+///
+/// #[derive(Bundle)]
+/// #[bundle(C)]
+/// struct Unit {
+///     health: u32,
+///     name: String,
+/// }
+///
+/// let mut world = WorldStorage::<C, ()>::default();
+/// let rat = world.spawn_bundle(Unit {
+///     health: 2,
+///     name: "Rat".to_string(),
+/// });
+/// assert_eq!(world.cmps.health.get(&rat), Some(&2));
+/// ```
+pub trait Bundle<C>: Sized {
+    /// Insert all the components. Does not validate the entity.
+    fn insert_into(self, cmps: &mut C, entity: Entity);
+    /// Remove all the components, only if the entity has every one of them.
+    fn remove_from(cmps: &mut C, entity: Entity) -> Option<Self>;
+}
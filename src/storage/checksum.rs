@@ -0,0 +1,84 @@
+use std::hash::Hasher;
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// FNV-1a hasher, used for the world checksums.
+/// Unlike the std `DefaultHasher` it is guaranteed to give the same results
+/// across the builds and platforms (integers are hashed as little endian
+/// and `usize` as `u64`).
+pub struct StableHasher(u64);
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self(FNV_OFFSET)
+    }
+}
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16);
+    }
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32);
+    }
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64);
+    }
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128);
+    }
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hash::Hash;
+
+    fn hash(value: impl Hash) -> u64 {
+        let mut hasher = StableHasher::default();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn known_values() {
+        // reference FNV-1a values
+        assert_eq!(StableHasher::default().finish(), 0xcbf29ce484222325);
+        let mut hasher = StableHasher::default();
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn platform_independent_integers() {
+        assert_eq!(hash(1_usize), hash(1_u64));
+        assert_eq!(hash(-1_i32), hash(u32::MAX));
+        assert_ne!(hash(1_u32), hash(2_u32));
+    }
+}
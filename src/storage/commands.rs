@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+
+use super::components::{ComponentSet, ComponentStorage};
+use super::entity::{Entity, Reservations};
+use super::error::WorldError;
+use super::relation::{Relation, RelationKind};
+use super::world::WorldStorage;
+
+// gets the retargeting of the recorded entities
+type CommandFn<C, R> = dyn FnOnce(&mut WorldStorage<C, R>, &dyn Fn(Entity) -> Entity);
+
+enum Command<C, R> {
+    Despawn(Entity),
+    Apply(Box<CommandFn<C, R>>),
+}
+
+/// Buffer of deferred structural changes (spawns, despawns, insertions,
+/// removals and relations). Allows to record the changes while the world is borrowed
+/// (e.g. by a query) and replay them later on.
+/// ```
+/// use wunderkammer::prelude::*;
+///
+/// #[derive(ComponentSet, Default)]
+/// struct C {
+///     pub health: ComponentStorage<u32>,
+///     pub loot: ComponentStorage<()>,
+/// }
+/// let mut world = WorldStorage::<C, ()>::default();
+/// let rat = world.spawn();
+/// insert!(world, health, rat, 0);
+///
+/// let mut commands = world.commands();
+/// for (entity, health) in query_iter!(world, With(health)) {
+///     if *health == 0 {
+///         commands.despawn(entity);
+///         let loot = commands.spawn();
+///         commands.insert(loot, |c| &mut c.loot, ());
+///     }
+/// }
+/// commands.apply(&mut world);
+///
+/// assert!(!world.is_valid(&rat));
+/// assert_eq!(query!(world, With(loot)).count(), 1);
+/// ```
+pub struct Commands<C, R> {
+    commands: Vec<Command<C, R>>,
+    reserved: Vec<Entity>,
+    reservations: Reservations,
+}
+impl<C: ComponentSet + 'static, R: Default + 'static> Commands<C, R> {
+    pub(crate) fn new(reservations: Reservations) -> Self {
+        Self {
+            commands: Vec::new(),
+            reserved: Vec::new(),
+            reservations,
+        }
+    }
+    /// Reserve a new entity. It can be immediately used as a target
+    /// of the subsequent commands in the same buffer.
+    /// The reserved entity is spawned (without components) on the next
+    /// spawn or despawn in the world, or when the buffer is applied.
+    /// Panics if the id space is exhausted.
+    pub fn spawn(&mut self) -> Entity {
+        self.try_spawn().expect("Entity id space exhausted!")
+    }
+    /// Reserve a new entity, or return an error if the id space is exhausted.
+    pub fn try_spawn(&mut self) -> Result<Entity, WorldError> {
+        let entity = self.reservations.reserve()?;
+        self.reserved.push(entity);
+        Ok(entity)
+    }
+    pub fn despawn(&mut self, entity: Entity) {
+        self.commands.push(Command::Despawn(entity));
+    }
+    /// Insert a component into the storage selected by the accessor
+    /// (e.g. `|c| &mut c.health`).
+    /// Like `insert!`, does nothing if the entity is not valid at the time
+    /// of application.
+    pub fn insert<T: 'static>(
+        &mut self,
+        entity: Entity,
+        storage: fn(&mut C) -> &mut ComponentStorage<T>,
+        value: T,
+    ) {
+        self.commands
+            .push(Command::Apply(Box::new(move |world, target| {
+                let entity = target(entity);
+                if world.is_valid(&entity) {
+                    storage(&mut world.cmps).__insert(entity, value);
+                }
+            })));
+    }
+    /// Remove a component from the storage selected by the accessor
+    /// (e.g. `|c| &mut c.health`).
+    pub fn remove<T: 'static>(
+        &mut self,
+        entity: Entity,
+        storage: fn(&mut C) -> &mut ComponentStorage<T>,
+    ) {
+        self.commands
+            .push(Command::Apply(Box::new(move |world, target| {
+                let _ = storage(&mut world.cmps).remove(target(entity));
+            })));
+    }
+    /// Relate the source with the target in the relation selected by
+    /// the accessor (e.g. `|c| &mut c.owns`).
+    /// Does nothing if either of the entities is not valid at the time
+    /// of application.
+    pub fn relate<K: RelationKind + 'static>(
+        &mut self,
+        source: Entity,
+        storage: fn(&mut C) -> &mut Relation<K>,
+        target: Entity,
+    ) {
+        self.commands
+            .push(Command::Apply(Box::new(move |world, retarget| {
+                let (source, target) = (retarget(source), retarget(target));
+                if world.is_valid(&source) && world.is_valid(&target) {
+                    storage(&mut world.cmps).__insert(source, target);
+                }
+            })));
+    }
+    /// Remove a single relation from the relation selected by the accessor
+    /// (e.g. `|c| &mut c.owns`).
+    pub fn unrelate<K: RelationKind + 'static>(
+        &mut self,
+        source: Entity,
+        storage: fn(&mut C) -> &mut Relation<K>,
+        target: Entity,
+    ) {
+        self.commands
+            .push(Command::Apply(Box::new(move |world, retarget| {
+                storage(&mut world.cmps).unrelate(retarget(source), retarget(target));
+            })));
+    }
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty() && self.reserved.is_empty()
+    }
+    /// Spawn the reserved entities and replay the recorded commands in order.
+    /// Returns the spawned entities.
+    /// In case the world's entities have been replaced since the buffer was
+    /// created (e.g. by `apply_patch` or another world altogether),
+    /// new entities are spawned instead of the reserved ones.
+    /// The commands are then retargeted accordingly (but entities stored
+    /// inside of the component values are not).
+    pub fn apply(self, world: &mut WorldStorage<C, R>) -> Vec<Entity> {
+        let mut remapped = HashMap::new();
+        let spawned = if world.entities.owns(&self.reservations) {
+            world.entities.flush();
+            self.reserved
+        } else {
+            self.reserved
+                .iter()
+                .map(|&reserved| {
+                    let entity = world.spawn();
+                    if entity != reserved {
+                        remapped.insert(reserved, entity);
+                    }
+                    entity
+                })
+                .collect()
+        };
+
+        let target = |entity: Entity| *remapped.get(&entity).unwrap_or(&entity);
+
+        for command in self.commands {
+            match command {
+                Command::Despawn(entity) => world.despawn(target(entity)),
+                Command::Apply(f) => f(world, &target),
+            }
+        }
+        spawned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    struct Owns;
+    impl RelationKind for Owns {
+        const ARITY: Arity = Arity::OneToMany;
+        const ON_DESPAWN: OnDespawn = OnDespawn::Remove;
+    }
+
+    #[derive(ComponentSet, Default)]
+    struct C {
+        pub health: ComponentStorage<u32>,
+        pub name: ComponentStorage<String>,
+        pub owns: Relation<Owns>,
+    }
+    #[derive(Default)]
+    struct R;
+
+    #[test]
+    fn spawn_and_insert() {
+        let mut w = WorldStorage::<C, R>::default();
+        let _ = w.spawn();
+
+        let mut commands = w.commands();
+        let a = commands.spawn();
+        let b = commands.spawn();
+        commands.insert(a, |c| &mut c.health, 15);
+        commands.insert(b, |c| &mut c.name, "Sixteen".to_string());
+        assert!(!w.is_valid(&a));
+
+        let spawned = commands.apply(&mut w);
+        assert_eq!(spawned, vec![a, b]);
+        assert_eq!(w.cmps.health.get(&a), Some(&15));
+        assert_eq!(w.cmps.name.get(&b), Some(&"Sixteen".to_string()));
+    }
+
+    #[test]
+    fn reserve_recycled() {
+        let mut w = WorldStorage::<C, R>::default();
+        let a = w.spawn();
+        let _ = w.spawn();
+        w.despawn(a);
+
+        let mut commands = w.commands();
+        let b = commands.spawn();
+        assert_eq!(b.id, a.id);
+        commands.apply(&mut w);
+        assert!(w.is_valid(&b));
+    }
+
+    #[test]
+    fn despawn_and_remove_during_query() {
+        let mut w = WorldStorage::<C, R>::default();
+        let a = w.spawn();
+        let b = w.spawn();
+        insert!(w, health, a, 0);
+        insert!(w, name, a, "Zero".to_string());
+        insert!(w, health, b, 5);
+        insert!(w, name, b, "Five".to_string());
+
+        let mut commands = w.commands();
+        for (entity, health) in query_iter!(w, With(health)) {
+            match *health {
+                0 => commands.despawn(entity),
+                _ => commands.remove(entity, |c| &mut c.name),
+            }
+        }
+        commands.apply(&mut w);
+
+        assert!(!w.is_valid(&a));
+        assert_eq!(w.cmps.health.get(&b), Some(&5));
+        assert_eq!(w.cmps.name.get(&b), None);
+    }
+
+    #[test]
+    fn replay_in_order() {
+        let mut w = WorldStorage::<C, R>::default();
+        let a = w.spawn();
+
+        let mut commands = w.commands();
+        commands.insert(a, |c| &mut c.health, 1);
+        commands.insert(a, |c| &mut c.health, 2);
+        commands.despawn(a);
+        // ignored as the entity is already dead
+        commands.insert(a, |c| &mut c.health, 3);
+        commands.apply(&mut w);
+
+        assert_eq!(w.cmps.health.get(&a), None);
+    }
+
+    #[test]
+    fn relate_and_unrelate() {
+        let mut w = WorldStorage::<C, R>::default();
+        let a = w.spawn();
+        let b = w.spawn();
+        insert!(w, owns, a, b);
+
+        let mut commands = w.commands();
+        let item = commands.spawn();
+        commands.relate(a, |c| &mut c.owns, item);
+        commands.unrelate(a, |c| &mut c.owns, b);
+        // the target is not valid
+        commands.relate(b, |c| &mut c.owns, Entity { id: 7, version: 0 });
+        commands.apply(&mut w);
+
+        assert_eq!(w.cmps.owns.targets(&a), &[item]);
+        assert!(w.cmps.owns.targets(&b).is_empty());
+    }
+
+    #[test]
+    fn world_spawn_skips_reserved() {
+        let mut w = WorldStorage::<C, R>::default();
+        let a = w.spawn();
+        w.despawn(a);
+
+        let mut commands = w.commands();
+        let b = commands.spawn();
+        commands.insert(b, |c| &mut c.health, 15);
+
+        // the world has changed in the meantime
+        let c = w.spawn();
+        assert_ne!(b, c);
+        assert!(w.is_valid(&b));
+        let d = commands.spawn();
+        assert_ne!(d, c);
+        w.despawn(c);
+
+        let spawned = commands.apply(&mut w);
+        assert_eq!(spawned, vec![b, d]);
+        assert_eq!(w.cmps.health.get(&b), Some(&15));
+        assert!(w.is_valid(&d));
+    }
+
+    #[test]
+    fn two_buffers() {
+        let mut w = WorldStorage::<C, R>::default();
+        let mut first = w.commands();
+        let mut second = w.commands();
+        let a = first.spawn();
+        let b = second.spawn();
+        assert_ne!(a, b);
+        first.insert(a, |c| &mut c.health, 1);
+        second.insert(b, |c| &mut c.health, 2);
+
+        assert_eq!(second.apply(&mut w), vec![b]);
+        assert_eq!(first.apply(&mut w), vec![a]);
+        assert_eq!(w.cmps.health.get(&a), Some(&1));
+        assert_eq!(w.cmps.health.get(&b), Some(&2));
+        assert_eq!(w.entities().count(), 2);
+    }
+
+    #[test]
+    fn dropped_buffer() {
+        let mut w = WorldStorage::<C, R>::default();
+        let mut commands = w.commands();
+        let a = commands.spawn();
+        drop(commands);
+
+        // the reservation is released
+        assert_eq!(w.spawn(), a);
+        assert_eq!(w.entities().count(), 1);
+    }
+
+    #[test]
+    fn remap_replaced_entities() {
+        let w = WorldStorage::<C, R>::default();
+        let mut commands = w.commands();
+        let a = commands.spawn();
+        let item = commands.spawn();
+        commands.insert(a, |c| &mut c.health, 15);
+        commands.relate(a, |c| &mut c.owns, item);
+
+        let mut other = WorldStorage::<C, R>::default();
+        let b = other.spawn();
+        assert_eq!(a, b);
+
+        let spawned = commands.apply(&mut other);
+        assert_ne!(spawned[0], a);
+        assert_eq!(other.cmps.health.get(&spawned[0]), Some(&15));
+        assert_eq!(other.cmps.health.get(&b), None);
+        assert_eq!(other.cmps.owns.targets(&spawned[0]), &[spawned[1]]);
+    }
+}
@@ -1,101 +1,635 @@
 #[cfg(feature = "serialize")]
 use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 
 use super::entity::{Entity, IdSize};
+#[cfg(feature = "serialize")]
+use super::error::ComponentError;
+use super::map_entities::MapEntities;
+use super::schema::Schema;
 const TOMBSTONE: IdSize = IdSize::MAX;
 
+/// World time counter used for change detection.
+pub type Tick = u32;
+
+/// Serialized component value used by the string keyed access.
+#[cfg(feature = "serialize")]
+pub type JsonValue = serde_json::Value;
+
 /// Base trait for the `components` world field.
 /// Handles component cleanup after an entity is despawned from the world.
 pub trait ComponentSet {
     /// Despawn all the entity's components
     fn remove_all_components(&mut self, entity: Entity);
+    /// Static description of the components (e.g. for the editor tooling)
+    fn schema() -> Schema
+    where
+        Self: Sized;
     /// Get component entities by name (e.g. for scripting)
     fn entities_str(&self, component: &str) -> Vec<&Entity>;
+    /// Check whether the set has a component of a given name
+    fn is_component_str(component: &str) -> bool
+    where
+        Self: Sized;
+    /// Check whether the entity has the component of a given name
+    fn has_str(&self, component: &str, entity: Entity) -> bool;
+    /// Remove the entity's component by name.
+    /// For relations, only the ones where the entity is the source are removed.
+    /// Returns `false` if there was nothing to remove.
+    fn remove_str(&mut self, component: &str, entity: Entity) -> bool;
+    /// Get the entity's component by name, serialized as a JSON value
+    #[cfg(feature = "serialize")]
+    fn get_str_serialized(
+        &self,
+        component: &str,
+        entity: Entity,
+    ) -> Result<Option<JsonValue>, ComponentError>;
+    /// Insert the entity's component by name, deserialized from a JSON value.
+    /// Like `ComponentStorage::__insert` it does not validate the entity,
+    /// the caller must check it first (e.g. with `WorldStorage::validate`).
+    #[cfg(feature = "serialize")]
+    fn insert_str_serialized(
+        &mut self,
+        component: &str,
+        entity: Entity,
+        value: &JsonValue,
+    ) -> Result<(), ComponentError>;
+    /// Same as `insert_str_serialized`, but the entities contained in the
+    /// value are mapped. Only the relation targets and the
+    /// `#[component(map_entities)]` fields are mapped, the other values
+    /// are inserted unmapped (so fields holding entities need `map_entities`).
+    #[cfg(feature = "serialize")]
+    fn insert_str_serialized_mapped(
+        &mut self,
+        component: &str,
+        entity: Entity,
+        value: &JsonValue,
+        mapper: &mut dyn FnMut(Entity) -> Entity,
+    ) -> Result<(), ComponentError>;
+    /// Propagate the current world tick to all the component storages
+    fn set_tick(&mut self, tick: Tick);
+    /// Collect the entities that should be despawned together with the given one
+    /// (e.g. by relations with `OnDespawn::Cascade` policy)
+    fn cascade_despawn(&self, entity: &Entity) -> Vec<Entity>;
+    /// Move all the entity's components from the other set into this one,
+    /// under the mapped entity. Values of the `#[component(map_entities)]`
+    /// fields and relation targets are mapped as well.
+    /// Relations to the entities that can't be mapped (the mapper returns
+    /// `None`) are dropped, as are the `map_entities` values referencing them.
+    fn move_entity(
+        &mut self,
+        other: &mut Self,
+        entity: Entity,
+        mapper: &mut dyn FnMut(Entity) -> Option<Entity>,
+    );
+    /// Copy all the source entity's components to the destination one
+    /// (except for the `#[component(no_clone)]` fields)
+    fn clone_entity(&mut self, src: Entity, dst: Entity);
+    /// Per field changes, generated as a `{ComponentSetName}Patch` struct
+    type Patch;
+    /// Changes that turn this set into the other one
+    /// (except for the `no_clone` and `no_diff` fields)
+    fn diff(&self, other: &Self) -> Self::Patch;
+    /// Replay the changes created by `diff`
+    fn apply_patch(&mut self, patch: &Self::Patch);
+    /// Names of all the components the entity has
+    fn component_names(&self, entity: Entity) -> Vec<&'static str>;
+    /// List the entity's components with their values
+    /// (a placeholder for the `#[component(no_debug)]` fields)
+    fn debug_entity(&self, entity: Entity) -> String;
+    /// Hash the components in a canonical order
+    /// (except for the `#[component(no_hash)]` fields)
+    fn hash_components<H: Hasher>(&self, state: &mut H);
+}
+
+/// Component sets that can be forked. Implemented by the `ComponentSet`
+/// derive, unless some of the fields are `#[component(no_clone)]`.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` can't be forked",
+    note = "the `#[component(no_clone)]` fields can't be copied on write"
+)]
+pub trait ForkComponents {
+    /// Copy-on-write copy of all the component storages
+    fn fork(&mut self) -> Self;
 }
 
 /// Component storage based on a sparse set data structure.
-#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+/// Every stored component keeps the ticks of it's insertion and last
+/// (possible) modification, so the changes can be detected.
 pub struct ComponentStorage<T> {
+    data: Data<T>,
+    // data shared with the forks, copied on the first write
+    shared: Option<Arc<Data<T>>>,
+    fork: Option<ForkFn<T>>,
+    removed: Vec<Entity>,
+    tick: Tick,
+}
+
+type ForkFn<T> = fn(&Data<T>) -> Data<T>;
+
+#[derive(Clone)]
+struct Data<T> {
     dense: Vec<Entity>,
     sparse: Vec<IdSize>,
     values: Vec<T>,
+    added: Vec<Tick>,
+    changed: Vec<Tick>,
 }
+
 impl<T> ComponentStorage<T> {
     pub fn get(&self, entity: &Entity) -> Option<&T> {
-        self.values.get(self.get_dense_index(entity)?)
+        self.data().values.get(self.get_dense_index(entity)?)
     }
+    // Mutable access marks the component as changed.
     pub fn get_mut(&mut self, entity: &Entity) -> Option<&mut T> {
         let i = self.get_dense_index(entity)?;
-        self.values.get_mut(i)
+        self.unshare();
+        self.data.changed[i] = self.tick;
+        self.data.values.get_mut(i)
+    }
+    /// Mutable access to the components of several distinct entities at once.
+    /// Returns `None` if any of the components is missing
+    /// or the entities are not unique.
+    pub fn get_many_mut<const N: usize>(&mut self, entities: [&Entity; N]) -> Option<[&mut T; N]> {
+        let mut indices = [0; N];
+        for (i, entity) in entities.iter().enumerate() {
+            indices[i] = self.get_dense_index(entity)?;
+        }
+        // split the values in the ascending dense order
+        let mut order: [usize; N] = std::array::from_fn(|i| i);
+        order.sort_unstable_by_key(|&i| indices[i]);
+        if order.windows(2).any(|w| indices[w[0]] == indices[w[1]]) {
+            return None;
+        }
+        self.unshare();
+        for i in indices {
+            self.data.changed[i] = self.tick;
+        }
+        let mut values: [Option<&mut T>; N] = std::array::from_fn(|_| None);
+        let mut rest = self.data.values.as_mut_slice();
+        let mut offset = 0;
+        for i in order {
+            let (head, tail) = std::mem::take(&mut rest).split_at_mut(indices[i] - offset + 1);
+            values[i] = head.last_mut();
+            offset = indices[i] + 1;
+            rest = tail;
+        }
+        Some(values.map(|v| v.unwrap()))
     }
     // Return currently stored entities
-    pub fn entities(&self) -> impl Iterator<Item = &Entity> {
-        self.dense.iter()
+    pub fn entities(&self) -> std::slice::Iter<'_, Entity> {
+        self.data().dense.iter()
+    }
+    /// Number of stored components.
+    pub fn len(&self) -> usize {
+        self.data().dense.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.data().dense.is_empty()
+    }
+    pub fn contains(&self, entity: &Entity) -> bool {
+        self.get_dense_index(entity).is_some()
+    }
+    /// Iterate over the entities and their components (in the dense order).
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.into_iter()
+    }
+    /// Mutably iterate over the entities and their components.
+    /// All the components are marked as changed.
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        self.into_iter()
+    }
+    pub fn values(&self) -> std::slice::Iter<'_, T> {
+        self.data().values.iter()
+    }
+    /// Mutably iterate over the components.
+    /// All the components are marked as changed.
+    pub fn values_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.unshare();
+        self.data.changed.fill(self.tick);
+        self.data.values.iter_mut()
+    }
+    /// Keep only the components matching the predicate.
+    pub fn retain(&mut self, mut f: impl FnMut(&Entity, &T) -> bool) {
+        // iterate backwards, so the swapped elements are already visited
+        for i in (0..self.len()).rev() {
+            let entity = self.data().dense[i];
+            if !f(&entity, &self.data().values[i]) {
+                let _ = self.remove(entity);
+            }
+        }
+    }
+    /// Remove all the components, returning them with their entities.
+    pub fn drain(&mut self) -> impl Iterator<Item = (Entity, T)> + '_ {
+        self.unshare();
+        self.data.sparse.clear();
+        self.data.added.clear();
+        self.data.changed.clear();
+        self.removed.extend_from_slice(&self.data.dense);
+        self.data.dense.drain(..).zip(self.data.values.drain(..))
+    }
+    pub fn clear(&mut self) {
+        let _ = self.drain();
+    }
+    /// Move a single component from the other storage into this one,
+    /// under the mapped entity (it's dropped if the entity can't be mapped).
+    pub fn move_entity(
+        &mut self,
+        other: &mut Self,
+        entity: Entity,
+        mapper: &mut dyn FnMut(Entity) -> Option<Entity>,
+    ) {
+        if let (Some(value), Some(mapped)) = (other.remove(entity), mapper(entity)) {
+            self.__insert(mapped, value);
+        }
+    }
+    /// Used by the `ComponentSet` derive for the `#[component(map_entities)]`
+    /// fields. The value is dropped if it references an entity that can't
+    /// be mapped.
+    pub fn __move_entity_mapped(
+        &mut self,
+        other: &mut Self,
+        entity: Entity,
+        mapper: &mut dyn FnMut(Entity) -> Option<Entity>,
+    ) where
+        T: MapEntities,
+    {
+        let (Some(mut value), Some(mapped)) = (other.remove(entity), mapper(entity)) else {
+            return;
+        };
+        let mut complete = true;
+        value.map_entities(&mut |e| {
+            mapper(e).unwrap_or_else(|| {
+                complete = false;
+                e
+            })
+        });
+        if complete {
+            self.__insert(mapped, value);
+        }
+    }
+    /// Reserve capacity for at least `additional` more components.
+    pub fn reserve(&mut self, additional: usize) {
+        self.unshare();
+        self.data.dense.reserve(additional);
+        self.data.values.reserve(additional);
+        self.data.added.reserve(additional);
+        self.data.changed.reserve(additional);
+    }
+    pub fn shrink_to_fit(&mut self) {
+        self.unshare();
+        // trailing tombstones are not needed
+        let sparse_len = self.data.dense.iter().map(|e| e.id as usize + 1).max();
+        self.data.sparse.truncate(sparse_len.unwrap_or(0));
+        self.data.sparse.shrink_to_fit();
+        self.data.dense.shrink_to_fit();
+        self.data.values.shrink_to_fit();
+        self.data.added.shrink_to_fit();
+        self.data.changed.shrink_to_fit();
     }
     // Insert a new component for the entity.
-    // Overwrite if already exists.
+    // Overwrite if already exists, returning the previous value.
     // Since it cannot validate the entity,
     // it is recommended to use `insert!` macro that calls it internally.
-    pub fn __insert(&mut self, entity: Entity, value: T) {
+    pub fn __insert(&mut self, entity: Entity, value: T) -> Option<T> {
+        self.unshare();
         // check if replacement
         if let Some(index) = self.get_dense_index(&entity) {
-            self.values[index] = value;
-            return;
+            self.data.changed[index] = self.tick;
+            return Some(std::mem::replace(&mut self.data.values[index], value));
         }
 
+        let data = &mut self.data;
         let index = entity.id as usize;
-        if index >= self.sparse.len() {
+        if index >= data.sparse.len() {
             // fill empty values with tombstones
-            self.sparse.resize(index + 1, TOMBSTONE);
+            data.sparse.resize(index + 1, TOMBSTONE);
         }
 
         // sparse array points to the element in the dense one
-        self.sparse[index] = self.dense.len() as IdSize;
+        data.sparse[index] = data.dense.len() as IdSize;
         // we push the element at the end of the dense array
-        self.dense.push(entity);
-        // components array is kept in sync with the dense array
-        self.values.push(value);
+        data.dense.push(entity);
+        // components and ticks arrays are kept in sync with the dense array
+        data.values.push(value);
+        data.added.push(self.tick);
+        data.changed.push(self.tick);
+        None
     }
 
     // Removes component for a given entity
     // Keeps the values densely packed
     pub fn remove(&mut self, entity: Entity) -> Option<T> {
         let removed_idx = self.get_dense_index(&entity)?;
+        self.unshare();
+        let data = &mut self.data;
 
         // we are going to swap the removed value with the last one first
-        let last_idx = self.dense.len() - 1;
-        let swapped_sparse_idx = self.dense[last_idx].id as usize;
+        let last_idx = data.dense.len() - 1;
+        let swapped_sparse_idx = data.dense[last_idx].id as usize;
 
-        self.dense.swap(removed_idx, last_idx);
-        self.values.swap(removed_idx, last_idx);
+        data.dense.swap(removed_idx, last_idx);
+        data.values.swap(removed_idx, last_idx);
+        data.added.swap(removed_idx, last_idx);
+        data.changed.swap(removed_idx, last_idx);
 
         // now remove the last element
-        let _ = self.dense.pop();
-        let removed = self.values.pop();
+        let _ = data.dense.pop();
+        let _ = data.added.pop();
+        let _ = data.changed.pop();
+        let removed = data.values.pop();
 
         // now fix the sparse vec
-        self.sparse[swapped_sparse_idx] = removed_idx as IdSize;
-        self.sparse[entity.id as usize] = TOMBSTONE;
+        data.sparse[swapped_sparse_idx] = removed_idx as IdSize;
+        data.sparse[entity.id as usize] = TOMBSTONE;
 
+        self.removed.push(entity);
         removed
     }
+    /// Used by the `ComponentSet` derive.
+    pub fn __remove_str(&mut self, entity: Entity) -> bool {
+        self.remove(entity).is_some()
+    }
+
+    /// Checks whether the entity's component has been inserted at or after
+    /// the given tick.
+    /// Ticks are compared with wrap around, so `since` should be less
+    /// than `Tick::MAX / 2` ticks old.
+    pub fn is_added(&self, entity: &Entity, since: Tick) -> bool {
+        self.get_dense_index(entity)
+            .is_some_and(|i| is_at_or_after(self.data().added[i], since))
+    }
+    /// Checks whether the entity's component has been inserted or mutably
+    /// accessed at or after the given tick.
+    /// Ticks are compared with wrap around, as in `is_added`.
+    pub fn is_changed(&self, entity: &Entity, since: Tick) -> bool {
+        self.get_dense_index(entity)
+            .is_some_and(|i| is_at_or_after(self.data().changed[i], since))
+    }
+    /// Returns entities that had this component removed during the current
+    /// tick (since the last drain).
+    /// The list is cleared when the tick advances, so it should be drained
+    /// before that.
+    pub fn drain_removed(&mut self) -> impl Iterator<Item = Entity> + '_ {
+        self.removed.drain(..)
+    }
+    /// Set the tick used to mark subsequent insertions and modifications.
+    /// Normally called through `WorldStorage::increment_tick`.
+    pub fn set_tick(&mut self, tick: Tick) {
+        if tick != self.tick {
+            self.removed.clear();
+        }
+        self.tick = tick;
+    }
 
     fn get_dense_index(&self, entity: &Entity) -> Option<usize> {
-        let i = *self.sparse.get(entity.id as usize)? as usize;
+        let data = self.data();
+        let i = *data.sparse.get(entity.id as usize)? as usize;
         // validate version
-        match self.dense.get(i)? == entity {
+        match data.dense.get(i)? == entity {
             false => None,
             true => Some(i),
         }
     }
+    fn data(&self) -> &Data<T> {
+        self.shared.as_deref().unwrap_or(&self.data)
+    }
+    // Take over the data shared with the forks, before it is modified.
+    fn unshare(&mut self) {
+        let Some(shared) = self.shared.take() else {
+            return;
+        };
+        self.data = Arc::try_unwrap(shared).unwrap_or_else(|shared| {
+            // only the forked storages are shared
+            let fork = self.fork.expect("Shared storage can't be copied!");
+            fork(&shared)
+        });
+    }
+}
+impl<T: Clone> ComponentStorage<T> {
+    /// Copy the source entity's component (if exists) to the destination one.
+    pub fn clone_entity(&mut self, src: Entity, dst: Entity) {
+        if let Some(value) = self.get(&src).cloned() {
+            self.__insert(dst, value);
+        }
+    }
+    /// Cheap copy of the storage. The data is shared between the storages
+    /// until one of them is modified (copy-on-write).
+    pub fn fork(&mut self) -> Self {
+        if self.shared.is_none() {
+            self.shared = Some(Arc::new(std::mem::take(&mut self.data)));
+        }
+        self.fork = Some(Data::clone);
+        Self {
+            data: Data::default(),
+            shared: self.shared.clone(),
+            fork: self.fork,
+            removed: self.removed.clone(),
+            tick: self.tick,
+        }
+    }
+}
+/// Hashes the entities and their components in the entity id order
+/// (regardless of the dense order). The ticks are not included.
+impl<T: Hash> Hash for ComponentStorage<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let data = self.data();
+        data.dense.len().hash(state);
+        for &i in data.sparse.iter().filter(|&&i| i != TOMBSTONE) {
+            let i = i as usize;
+            data.dense[i].hash(state);
+            data.values[i].hash(state);
+        }
+    }
+}
+impl<T: Clone> Clone for ComponentStorage<T> {
+    fn clone(&self) -> Self {
+        Self {
+            data: self.data().clone(),
+            shared: None,
+            fork: self.fork,
+            removed: self.removed.clone(),
+            tick: self.tick,
+        }
+    }
+}
+
+pub type Iter<'a, T> = std::iter::Zip<std::slice::Iter<'a, Entity>, std::slice::Iter<'a, T>>;
+pub type IterMut<'a, T> = std::iter::Zip<std::slice::Iter<'a, Entity>, std::slice::IterMut<'a, T>>;
+
+impl<'a, T> IntoIterator for &'a ComponentStorage<T> {
+    type Item = (&'a Entity, &'a T);
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let data = self.data();
+        data.dense.iter().zip(data.values.iter())
+    }
+}
+impl<'a, T> IntoIterator for &'a mut ComponentStorage<T> {
+    type Item = (&'a Entity, &'a mut T);
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.unshare();
+        self.data.changed.fill(self.tick);
+        self.data.dense.iter().zip(self.data.values.iter_mut())
+    }
+}
+impl<T> IntoIterator for ComponentStorage<T> {
+    type Item = (Entity, T);
+    type IntoIter = std::iter::Zip<std::vec::IntoIter<Entity>, std::vec::IntoIter<T>>;
+
+    fn into_iter(mut self) -> Self::IntoIter {
+        self.unshare();
+        self.data.dense.into_iter().zip(self.data.values)
+    }
+}
+/// Like `__insert`, does not validate the entities.
+impl<T> Extend<(Entity, T)> for ComponentStorage<T> {
+    fn extend<I: IntoIterator<Item = (Entity, T)>>(&mut self, iter: I) {
+        for (entity, value) in iter {
+            self.__insert(entity, value);
+        }
+    }
 }
 impl<T> Default for ComponentStorage<T> {
+    fn default() -> Self {
+        Self {
+            data: Data::default(),
+            shared: None,
+            fork: None,
+            removed: Vec::new(),
+            tick: 0,
+        }
+    }
+}
+/// Wrap around aware tick comparison.
+fn is_at_or_after(tick: Tick, since: Tick) -> bool {
+    tick.wrapping_sub(since) <= Tick::MAX / 2
+}
+
+impl<T> Default for Data<T> {
     fn default() -> Self {
         Self {
             dense: Vec::new(),
             sparse: Vec::new(),
             values: Vec::new(),
+            added: Vec::new(),
+            changed: Vec::new(),
+        }
+    }
+}
+
+// The storage is serialized as a plain struct, regardless of the sharing.
+#[cfg(feature = "serialize")]
+#[derive(Serialize)]
+#[serde(rename = "ComponentStorage")]
+struct SerializeStorage<'a, T> {
+    dense: &'a Vec<Entity>,
+    sparse: &'a Vec<IdSize>,
+    values: &'a Vec<T>,
+    added: &'a Vec<Tick>,
+    changed: &'a Vec<Tick>,
+    removed: &'a Vec<Entity>,
+    tick: Tick,
+}
+// The change detection fields are missing in the storages saved by the
+// older versions.
+#[cfg(feature = "serialize")]
+#[derive(Deserialize)]
+#[serde(rename = "ComponentStorage")]
+struct DeserializeStorage<T> {
+    dense: Vec<Entity>,
+    sparse: Vec<IdSize>,
+    values: Vec<T>,
+    #[serde(default)]
+    added: Option<Vec<Tick>>,
+    #[serde(default)]
+    changed: Option<Vec<Tick>>,
+    #[serde(default)]
+    removed: Vec<Entity>,
+    #[serde(default)]
+    tick: Tick,
+}
+#[cfg(feature = "serialize")]
+impl<T: Serialize> Serialize for ComponentStorage<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let data = self.data();
+        SerializeStorage {
+            dense: &data.dense,
+            sparse: &data.sparse,
+            values: &data.values,
+            added: &data.added,
+            changed: &data.changed,
+            removed: &self.removed,
+            tick: self.tick,
         }
+        .serialize(serializer)
+    }
+}
+#[cfg(feature = "serialize")]
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for ComponentStorage<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let storage = DeserializeStorage::deserialize(deserializer)?;
+        let ticks = || vec![0; storage.dense.len()];
+        Ok(Self {
+            data: Data {
+                added: storage.added.unwrap_or_else(ticks),
+                changed: storage.changed.unwrap_or_else(ticks),
+                dense: storage.dense,
+                sparse: storage.sparse,
+                values: storage.values,
+            },
+            shared: None,
+            fork: None,
+            removed: storage.removed,
+            tick: storage.tick,
+        })
+    }
+}
+#[cfg(feature = "serialize")]
+impl<T: Serialize> ComponentStorage<T> {
+    /// Get the entity's component serialized as a JSON value.
+    pub fn get_serialized(&self, entity: &Entity) -> Result<Option<JsonValue>, serde_json::Error> {
+        self.get(entity).map(serde_json::to_value).transpose()
+    }
+}
+#[cfg(feature = "serialize")]
+impl<T: serde::de::DeserializeOwned> ComponentStorage<T> {
+    /// Insert a component deserialized from a JSON value.
+    /// As `__insert` it does not validate the entity.
+    pub fn __insert_serialized(
+        &mut self,
+        entity: Entity,
+        value: &JsonValue,
+    ) -> Result<Option<T>, serde_json::Error> {
+        let value = T::deserialize(value)?;
+        Ok(self.__insert(entity, value))
+    }
+    /// Insert a component deserialized from a JSON value,
+    /// modified by the given function first.
+    pub fn __insert_serialized_with(
+        &mut self,
+        entity: Entity,
+        value: &JsonValue,
+        f: impl FnOnce(&mut T),
+    ) -> Result<Option<T>, serde_json::Error> {
+        let mut value = T::deserialize(value)?;
+        f(&mut value);
+        Ok(self.__insert(entity, value))
+    }
+    /// Used by the `ComponentSet` derive for the fields without
+    /// `#[component(map_entities)]`. Unlike in `Relation`, there are no targets
+    /// here, so the value is inserted as it is and the mapper is not called.
+    pub fn __insert_serialized_targets(
+        &mut self,
+        entity: Entity,
+        value: &JsonValue,
+        _mapper: &mut dyn FnMut(Entity) -> Entity,
+    ) -> Result<Option<T>, serde_json::Error> {
+        self.__insert_serialized(entity, value)
     }
 }
 
@@ -111,8 +645,8 @@ mod tests {
         let entity = Entity { id: 0, version: 0 };
         storage.__insert(entity, "VALUE");
 
-        assert_eq!(storage.dense.len(), 1);
-        assert_eq!(storage.values.len(), 1);
+        assert_eq!(storage.data.dense.len(), 1);
+        assert_eq!(storage.data.values.len(), 1);
         assert_eq!(storage.get(&entity), Some(&"VALUE"));
     }
 
@@ -127,8 +661,8 @@ mod tests {
         let entity = Entity { id: 2, version: 0 };
         storage.__insert(entity, "VALUE_NEW".to_string());
 
-        assert_eq!(storage.dense.len(), 5);
-        assert_eq!(storage.values.len(), 5);
+        assert_eq!(storage.data.dense.len(), 5);
+        assert_eq!(storage.data.values.len(), 5);
         assert_eq!(storage.get(&entity), Some(&"VALUE_NEW".to_string()));
     }
 
@@ -144,9 +678,9 @@ mod tests {
             storage.__insert(entity, 10 * i);
         }
 
-        assert_eq!(storage.dense.len(), 5);
-        assert_eq!(storage.values.len(), 5);
-        assert_eq!(storage.sparse.len(), 10);
+        assert_eq!(storage.data.dense.len(), 5);
+        assert_eq!(storage.data.values.len(), 5);
+        assert_eq!(storage.data.sparse.len(), 10);
         assert_eq!(storage.entities().collect::<Vec<_>>().len(), 5);
 
         for i in 0..10 {
@@ -192,8 +726,8 @@ mod tests {
         storage.__insert(entity, "VALUE");
         storage.remove(entity);
 
-        assert_eq!(storage.dense.len(), 0);
-        assert_eq!(storage.values.len(), 0);
+        assert_eq!(storage.data.dense.len(), 0);
+        assert_eq!(storage.data.values.len(), 0);
         assert_eq!(storage.get(&entity), None);
     }
 
@@ -209,7 +743,7 @@ mod tests {
         let entity_0r = Entity { id: 0, version: 1 };
         storage.__insert(entity_0r, "VALUE0r");
 
-        assert_eq!(storage.dense.len(), 2);
+        assert_eq!(storage.data.dense.len(), 2);
         assert!(!storage
             .entities()
             .collect::<HashSet<_>>()
@@ -223,8 +757,8 @@ mod tests {
             let entity = Entity { id: i, version: 0 };
             storage.__insert(entity, 10 * i);
         }
-        assert_eq!(storage.dense.len(), 10);
-        assert_eq!(storage.values.len(), 10);
+        assert_eq!(storage.data.dense.len(), 10);
+        assert_eq!(storage.data.values.len(), 10);
         assert_eq!(storage.entities().collect::<Vec<_>>().len(), 10);
 
         for i in 0..10 {
@@ -234,8 +768,8 @@ mod tests {
             }
         }
 
-        assert_eq!(storage.dense.len(), 5);
-        assert_eq!(storage.values.len(), 5);
+        assert_eq!(storage.data.dense.len(), 5);
+        assert_eq!(storage.data.values.len(), 5);
         assert_eq!(storage.entities().collect::<Vec<_>>().len(), 5);
 
         for i in 0..10 {
@@ -265,4 +799,289 @@ mod tests {
 
         assert_eq!(storage.get(&Entity { id: 0, version: 1 }), None);
     }
+
+    #[test]
+    fn added_ticks() {
+        let mut storage = ComponentStorage::default();
+        let a = Entity { id: 0, version: 0 };
+        let b = Entity { id: 1, version: 0 };
+        storage.__insert(a, "A");
+        storage.set_tick(1);
+        storage.__insert(b, "B");
+
+        assert!(storage.is_added(&a, 0));
+        assert!(!storage.is_added(&a, 1));
+        assert!(storage.is_added(&b, 1));
+
+        // replacement does not count as an addition
+        storage.set_tick(2);
+        storage.__insert(a, "A2");
+        assert!(!storage.is_added(&a, 2));
+        assert!(storage.is_changed(&a, 2));
+    }
+
+    #[test]
+    fn changed_ticks() {
+        let mut storage = ComponentStorage::default();
+        let a = Entity { id: 0, version: 0 };
+        let b = Entity { id: 1, version: 0 };
+        storage.__insert(a, 1);
+        storage.__insert(b, 2);
+        storage.set_tick(1);
+
+        *storage.get_mut(&b).unwrap() += 1;
+        assert!(!storage.is_changed(&a, 1));
+        assert!(storage.is_changed(&b, 1));
+
+        // ticks follow the values when swapped on removal
+        storage.remove(a);
+        assert!(storage.is_changed(&b, 1));
+        assert!(!storage.is_added(&b, 1));
+    }
+
+    #[test]
+    fn ticks_wrap_around() {
+        let mut storage = ComponentStorage::default();
+        let a = Entity { id: 0, version: 0 };
+        let b = Entity { id: 1, version: 0 };
+        storage.set_tick(Tick::MAX);
+        storage.__insert(a, 1);
+        storage.set_tick(Tick::MAX.wrapping_add(1));
+        storage.__insert(b, 2);
+
+        assert!(storage.is_added(&a, Tick::MAX));
+        assert!(storage.is_added(&b, Tick::MAX));
+        assert!(!storage.is_added(&a, 0));
+        assert!(storage.is_added(&b, 0));
+    }
+
+    #[test]
+    fn drain_removed() {
+        let mut storage = ComponentStorage::default();
+        let a = Entity { id: 0, version: 0 };
+        let b = Entity { id: 1, version: 0 };
+        storage.__insert(a, 1);
+        storage.__insert(b, 2);
+        storage.remove(b);
+        // removing missing component is not recorded
+        storage.remove(b);
+
+        assert_eq!(storage.drain_removed().collect::<Vec<_>>(), vec![b]);
+        assert_eq!(storage.drain_removed().count(), 0);
+
+        // not drained removals are discarded when the tick advances
+        storage.remove(a);
+        storage.set_tick(1);
+        assert_eq!(storage.drain_removed().count(), 0);
+    }
+
+    #[test]
+    fn get_many_mut() {
+        let mut storage = ComponentStorage::default();
+        let a = Entity { id: 0, version: 0 };
+        let b = Entity { id: 1, version: 0 };
+        let c = Entity { id: 2, version: 0 };
+        storage.__insert(a, 10);
+        storage.__insert(b, 20);
+        storage.set_tick(1);
+
+        let [va, vb] = storage.get_many_mut([&b, &a]).unwrap();
+        std::mem::swap(va, vb);
+        assert_eq!(storage.get(&a), Some(&20));
+        assert_eq!(storage.get(&b), Some(&10));
+        assert!(storage.is_changed(&a, 1));
+
+        // duplicates
+        assert!(storage.get_many_mut([&a, &a]).is_none());
+        // missing
+        assert!(storage.get_many_mut([&a, &c]).is_none());
+
+        storage.__insert(c, 30);
+        let [vc, va, vb] = storage.get_many_mut([&c, &a, &b]).unwrap();
+        assert_eq!((*va, *vb, *vc), (20, 10, 30));
+        assert!(storage.get_many_mut([&c, &b, &c]).is_none());
+    }
+
+    #[test]
+    fn iter() {
+        let mut storage = ComponentStorage::default();
+        storage.extend((0..5).map(|i| (Entity { id: i, version: 0 }, i * 10)));
+        assert_eq!(storage.len(), 5);
+        assert!(storage.contains(&Entity { id: 3, version: 0 }));
+        assert!(!storage.contains(&Entity { id: 3, version: 1 }));
+
+        assert_eq!(storage.values().sum::<IdSize>(), 100);
+        assert!(storage.iter().all(|(e, v)| e.id * 10 == *v));
+
+        storage.set_tick(1);
+        for (_, v) in storage.iter_mut() {
+            *v += 1;
+        }
+        for v in storage.values_mut() {
+            *v += 1;
+        }
+        assert_eq!((&storage).into_iter().map(|(_, v)| v).sum::<IdSize>(), 110);
+        assert!(storage.is_changed(&Entity { id: 0, version: 0 }, 1));
+
+        let mut owned = storage.into_iter().collect::<Vec<_>>();
+        owned.sort();
+        assert_eq!(owned[4], (Entity { id: 4, version: 0 }, 42));
+    }
+
+    #[test]
+    fn retain() {
+        let mut storage = ComponentStorage::default();
+        for i in 0..10 {
+            storage.__insert(Entity { id: i, version: 0 }, i);
+        }
+        storage.retain(|_, v| v % 3 == 0);
+
+        assert_eq!(storage.len(), 4);
+        for i in 0..10 {
+            let entity = Entity { id: i, version: 0 };
+            assert_eq!(storage.get(&entity).is_some(), i % 3 == 0);
+        }
+        assert_eq!(storage.drain_removed().count(), 6);
+    }
+
+    #[test]
+    fn drain_and_clear() {
+        let mut storage = ComponentStorage::default();
+        for i in 0..4 {
+            storage.__insert(Entity { id: i, version: 0 }, i);
+        }
+        let drained = storage.drain().collect::<Vec<_>>();
+        assert_eq!(drained.len(), 4);
+        assert!(storage.is_empty());
+        assert_eq!(storage.get(&Entity { id: 2, version: 0 }), None);
+        assert_eq!(storage.drain_removed().count(), 4);
+
+        // still usable
+        let entity = Entity { id: 2, version: 0 };
+        storage.__insert(entity, 7);
+        assert_eq!(storage.get(&entity), Some(&7));
+
+        storage.clear();
+        assert!(storage.is_empty());
+        assert_eq!(storage.get(&entity), None);
+    }
+
+    #[test]
+    fn reserve_and_shrink() {
+        let mut storage = ComponentStorage::default();
+        storage.reserve(100);
+        assert!(storage.data.values.capacity() >= 100);
+
+        for i in 0..10 {
+            storage.__insert(Entity { id: i, version: 0 }, i);
+        }
+        for i in 5..10 {
+            storage.remove(Entity { id: i, version: 0 });
+        }
+        storage.shrink_to_fit();
+        assert_eq!(storage.data.sparse.len(), 5);
+        assert_eq!(storage.data.values.capacity(), 5);
+        assert_eq!(storage.get(&Entity { id: 4, version: 0 }), Some(&4));
+    }
+
+    #[test]
+    fn clone() {
+        let mut storage = ComponentStorage::default();
+        let entity = Entity { id: 1, version: 0 };
+        storage.__insert(entity, 1);
+
+        let mut cloned = storage.clone();
+        *cloned.get_mut(&entity).unwrap() = 2;
+        assert_eq!(storage.get(&entity), Some(&1));
+        assert_eq!(cloned.get(&entity), Some(&2));
+    }
+
+    #[test]
+    fn fork_copy_on_write() {
+        let mut storage = ComponentStorage::default();
+        let a = Entity { id: 0, version: 0 };
+        let b = Entity { id: 1, version: 0 };
+        storage.__insert(a, 1);
+
+        let mut fork = storage.fork();
+        assert!(Arc::ptr_eq(
+            storage.shared.as_ref().unwrap(),
+            fork.shared.as_ref().unwrap()
+        ));
+        assert_eq!(fork.get(&a), Some(&1));
+
+        // reads and removals of missing components do not copy
+        assert_eq!(fork.remove(b), None);
+        assert!(fork.shared.is_some());
+
+        fork.__insert(b, 2);
+        *fork.get_mut(&a).unwrap() = 3;
+        assert!(fork.shared.is_none());
+        assert_eq!(storage.get(&a), Some(&1));
+        assert_eq!(storage.get(&b), None);
+        assert_eq!(fork.get(&b), Some(&2));
+
+        // the original takes the data back without copying
+        drop(fork);
+        let values = storage.shared.as_ref().unwrap().values.as_ptr();
+        *storage.get_mut(&a).unwrap() = 4;
+        assert_eq!(storage.data.values.as_ptr(), values);
+        assert_eq!(storage.get(&a), Some(&4));
+    }
+
+    #[test]
+    fn hash_in_id_order() {
+        use crate::storage::checksum::StableHasher;
+
+        let hash = |storage: &ComponentStorage<IdSize>| {
+            let mut hasher = StableHasher::default();
+            storage.hash(&mut hasher);
+            hasher.finish()
+        };
+        let mut a = ComponentStorage::default();
+        let mut b = ComponentStorage::default();
+        for i in 0..5 {
+            a.__insert(Entity { id: i, version: 0 }, i);
+            b.__insert(
+                Entity {
+                    id: 4 - i,
+                    version: 0,
+                },
+                4 - i,
+            );
+        }
+        assert_eq!(hash(&a), hash(&b));
+
+        a.remove(Entity { id: 2, version: 0 });
+        assert_ne!(hash(&a), hash(&b));
+    }
+
+    // the derived code does not depend on the prelude imports
+    #[cfg(test)]
+    mod without_prelude {
+        struct Owns;
+        impl crate::storage::RelationKind for Owns {
+            const ARITY: crate::storage::Arity = crate::storage::Arity::OneToMany;
+            const ON_DESPAWN: crate::storage::OnDespawn = crate::storage::OnDespawn::Cascade;
+        }
+        // shadows the prelude names
+        #[allow(dead_code)]
+        struct Entity;
+
+        #[derive(wunderkammer_derive::ComponentSet, Default)]
+        struct C {
+            pub health: crate::storage::ComponentStorage<u32>,
+            pub owns: crate::storage::Relation<Owns>,
+        }
+
+        #[test]
+        fn derive() {
+            let mut w = crate::storage::WorldStorage::<C, ()>::default();
+            let a = w.spawn();
+            let b = w.spawn();
+            w.cmps.owns.__insert(a, b);
+            w.despawn(b);
+            assert!(!w.is_valid(&a));
+        }
+    }
 }
@@ -0,0 +1,315 @@
+use super::components::{ComponentSet, JsonValue};
+use super::entity::Entity;
+use super::error::{ComponentError, ConsoleError, WorldError};
+use super::world::WorldStorage;
+
+impl<C: ComponentSet, R: Default> WorldStorage<C, R> {
+    /// Run a developer console command (e.g. typed in an in-game prompt)
+    /// and return it's text output.
+    /// Entities are written as `id:version` pairs and components
+    /// are accessed by their field names:
+    /// - `spawn` - outputs the new entity
+    /// - `despawn 12:0`
+    /// - `query health name !player` - outputs the entities that have all
+    ///   the listed components and none of the `!` prefixed ones
+    /// - `get 5:1 health` - outputs the component as JSON
+    /// - `set 5:1 health 10` - the rest of the line is parsed as JSON
+    /// - `remove 5:1 health`
+    /// ```
+    /// use wunderkammer::prelude::*;
+    ///
+    /// #[derive(ComponentSet, Default)]
+    /// struct Components {
+    ///     #[component(serialize)]
+    ///     pub health: ComponentStorage<u32>,
+    /// }
+    ///
+    /// let mut world = WorldStorage::<Components, ()>::default();
+    /// let entity = world.run_console_command("spawn").unwrap();
+    /// world.run_console_command(&format!("set {} health 10", entity)).unwrap();
+    /// assert_eq!(world.run_console_command("query health").unwrap(), "0:0");
+    /// assert_eq!(world.run_console_command("get 0:0 health").unwrap(), "10");
+    /// ```
+    pub fn run_console_command(&mut self, input: &str) -> Result<String, ConsoleError> {
+        let mut args = Args(input);
+        let Some(command) = args.next() else {
+            return Ok(String::new());
+        };
+        let output = match command {
+            "spawn" => {
+                args.end()?;
+                self.try_spawn()?.to_string()
+            }
+            "despawn" => {
+                let entity = args.entity()?;
+                args.end()?;
+                self.try_despawn(entity)?;
+                String::new()
+            }
+            "query" => self.console_query(args)?,
+            "get" => {
+                let entity = args.entity()?;
+                let component = args.required("component")?;
+                args.end()?;
+                self.validate(&entity)?;
+                self.cmps
+                    .get_str_serialized(component, entity)?
+                    .ok_or(WorldError::MissingComponent(entity))?
+                    .to_string()
+            }
+            "set" => {
+                let entity = args.entity()?;
+                let component = args.required("component")?;
+                let value = args.rest().ok_or(ConsoleError::MissingArgument("value"))?;
+                let value = serde_json::from_str::<JsonValue>(value)
+                    .map_err(|_| ConsoleError::InvalidJson(value.to_string()))?;
+                self.validate(&entity)?;
+                self.cmps.insert_str_serialized(component, entity, &value)?;
+                String::new()
+            }
+            "remove" => {
+                let entity = args.entity()?;
+                let component = args.required("component")?;
+                args.end()?;
+                self.validate(&entity)?;
+                if !self.cmps.remove_str(component, entity) {
+                    return Err(WorldError::MissingComponent(entity).into());
+                }
+                String::new()
+            }
+            _ => return Err(ConsoleError::UnknownCommand(command.to_string())),
+        };
+        Ok(output)
+    }
+
+    fn console_query(&self, args: Args) -> Result<String, ConsoleError> {
+        let (without, with): (Vec<_>, Vec<_>) = args.partition(|a| a.starts_with('!'));
+        let without = without.iter().map(|a| &a[1..]).collect::<Vec<_>>();
+
+        if let Some(unknown) = with
+            .iter()
+            .chain(without.iter())
+            .find(|c| !C::is_component_str(c))
+        {
+            return Err(ComponentError::UnknownComponent(unknown.to_string()).into());
+        }
+
+        let mut entities: Vec<Entity> = match with.first() {
+            Some(first) => self.cmps.entities_str(first).into_iter().copied().collect(),
+            None => self.entities().copied().collect(),
+        };
+        entities.retain(|e| {
+            with.iter().all(|c| self.cmps.has_str(c, *e))
+                && !without.iter().any(|c| self.cmps.has_str(c, *e))
+        });
+        entities.sort();
+        Ok(entities
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join(" "))
+    }
+}
+
+/// Whitespace separated command arguments.
+struct Args<'a>(&'a str);
+impl<'a> Iterator for Args<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let input = self.0.trim_start();
+        if input.is_empty() {
+            return None;
+        }
+        let end = input.find(char::is_whitespace).unwrap_or(input.len());
+        let (arg, rest) = input.split_at(end);
+        self.0 = rest;
+        Some(arg)
+    }
+}
+impl<'a> Args<'a> {
+    fn required(&mut self, name: &'static str) -> Result<&'a str, ConsoleError> {
+        self.next().ok_or(ConsoleError::MissingArgument(name))
+    }
+    fn entity(&mut self) -> Result<Entity, ConsoleError> {
+        let arg = self.required("entity")?;
+        let invalid = || ConsoleError::InvalidEntity(arg.to_string());
+        let (id, version) = arg.split_once(':').ok_or_else(invalid)?;
+        Ok(Entity {
+            id: id.parse().map_err(|_| invalid())?,
+            version: version.parse().map_err(|_| invalid())?,
+        })
+    }
+    /// Remaining (trimmed) part of the input.
+    fn rest(&mut self) -> Option<&'a str> {
+        let rest = std::mem::take(&mut self.0).trim();
+        (!rest.is_empty()).then_some(rest)
+    }
+    fn end(&mut self) -> Result<(), ConsoleError> {
+        match self.next() {
+            Some(arg) => Err(ConsoleError::UnexpectedArgument(arg.to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Debug, Deserialize, Hash, PartialEq, Serialize)]
+    struct Stats {
+        hp: u32,
+        name: String,
+    }
+
+    #[derive(ComponentSet, Default)]
+    struct C {
+        #[component(serialize)]
+        pub health: ComponentStorage<u32>,
+        #[component(serialize)]
+        pub stats: ComponentStorage<Stats>,
+        #[component(serialize)]
+        pub player: ComponentStorage<()>,
+    }
+
+    fn world() -> WorldStorage<C, ()> {
+        let mut w = WorldStorage::<C, ()>::default();
+        let a = w.spawn();
+        let b = w.spawn();
+        let c = w.spawn();
+        insert!(w, health, a, 5);
+        insert!(w, health, b, 7);
+        insert!(w, health, c, 9);
+        insert!(w, player, a, ());
+        insert!(w, player, c, ());
+        insert!(
+            w,
+            stats,
+            b,
+            Stats {
+                hp: 3,
+                name: "Rat".to_string()
+            }
+        );
+        w
+    }
+
+    #[test]
+    fn spawn_despawn() {
+        let mut w = world();
+        assert_eq!(w.run_console_command("spawn"), Ok("3:0".to_string()));
+        assert_eq!(w.run_console_command("  despawn   1:0 "), Ok(String::new()));
+        assert!(!w.is_valid(&Entity { id: 1, version: 0 }));
+        assert_eq!(w.run_console_command("spawn"), Ok("1:1".to_string()));
+        assert_eq!(w.run_console_command(""), Ok(String::new()));
+    }
+
+    #[test]
+    fn query() {
+        let mut w = world();
+        assert_eq!(
+            w.run_console_command("query"),
+            Ok("0:0 1:0 2:0".to_string())
+        );
+        assert_eq!(
+            w.run_console_command("query health player"),
+            Ok("0:0 2:0".to_string())
+        );
+        assert_eq!(
+            w.run_console_command("query health !player"),
+            Ok("1:0".to_string())
+        );
+        assert_eq!(w.run_console_command("query !health"), Ok(String::new()));
+    }
+
+    #[test]
+    fn get_set_remove() {
+        let mut w = world();
+        assert_eq!(
+            w.run_console_command("get 1:0 stats"),
+            Ok(r#"{"hp":3,"name":"Rat"}"#.to_string())
+        );
+        assert_eq!(
+            w.run_console_command(r#"set 0:0 stats { "hp": 10, "name": "Giant Rat" }"#),
+            Ok(String::new())
+        );
+        assert_eq!(
+            w.cmps.stats.get(&Entity { id: 0, version: 0 }),
+            Some(&Stats {
+                hp: 10,
+                name: "Giant Rat".to_string()
+            })
+        );
+        assert_eq!(w.run_console_command("set 0:0 health 1"), Ok(String::new()));
+        assert_eq!(w.run_console_command("get 0:0 health"), Ok("1".to_string()));
+        assert_eq!(
+            w.run_console_command("remove 0:0 health"),
+            Ok(String::new())
+        );
+        assert_eq!(
+            w.run_console_command("query !health"),
+            Ok("0:0".to_string())
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut w = world();
+        let a = Entity { id: 0, version: 0 };
+        assert_eq!(
+            w.run_console_command("jump"),
+            Err(ConsoleError::UnknownCommand("jump".to_string()))
+        );
+        assert_eq!(
+            w.run_console_command("get 0:0"),
+            Err(ConsoleError::MissingArgument("component"))
+        );
+        assert_eq!(
+            w.run_console_command("spawn 1"),
+            Err(ConsoleError::UnexpectedArgument("1".to_string()))
+        );
+        assert_eq!(
+            w.run_console_command("despawn 12"),
+            Err(ConsoleError::InvalidEntity("12".to_string()))
+        );
+        assert_eq!(
+            w.run_console_command("set 0:0 health ten"),
+            Err(ConsoleError::InvalidJson("ten".to_string()))
+        );
+        assert_eq!(
+            w.run_console_command("get 0:0 stats"),
+            Err(ConsoleError::World(WorldError::MissingComponent(a)))
+        );
+        assert_eq!(
+            w.run_console_command("query health !mana"),
+            Err(ConsoleError::Component(ComponentError::UnknownComponent(
+                "mana".to_string()
+            )))
+        );
+        assert_eq!(
+            w.run_console_command("get 0:0 mana"),
+            Err(ConsoleError::Component(ComponentError::UnknownComponent(
+                "mana".to_string()
+            )))
+        );
+        assert_eq!(
+            w.run_console_command("get 7:0 health"),
+            Err(ConsoleError::World(WorldError::UnknownId(Entity {
+                id: 7,
+                version: 0
+            })))
+        );
+        assert!(matches!(
+            w.run_console_command("set 0:0 health -1"),
+            Err(ConsoleError::Component(ComponentError::InvalidValue { .. }))
+        ));
+        assert_eq!(
+            w.run_console_command("despawn 0:3")
+                .unwrap_err()
+                .to_string(),
+            "Entity 0:3 has never been spawned"
+        );
+    }
+}
@@ -0,0 +1,191 @@
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+
+use super::components::ComponentStorage;
+use super::entity::Entity;
+use super::relation::{Relation, RelationKind};
+
+/// Storage that can compute the changes between two of it's states.
+/// Implemented for the component storages of `Clone + PartialEq` types
+/// and the relations.
+pub trait StorageDiff {
+    type Patch;
+    /// Changes that turn this storage into the other one.
+    fn diff(&self, other: &Self) -> Self::Patch;
+    /// Replay the changes. Does not validate the entities.
+    fn apply_patch(&mut self, patch: &Self::Patch);
+}
+
+/// Changes of a single storage, sorted by the entities.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct StoragePatch<T> {
+    pub inserted: Vec<(Entity, T)>,
+    pub changed: Vec<(Entity, T)>,
+    pub removed: Vec<Entity>,
+}
+impl<T> StoragePatch<T> {
+    pub fn is_empty(&self) -> bool {
+        self.inserted.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+impl<T> Default for StoragePatch<T> {
+    fn default() -> Self {
+        Self {
+            inserted: Vec::new(),
+            changed: Vec::new(),
+            removed: Vec::new(),
+        }
+    }
+}
+
+impl<T: Clone + PartialEq> StorageDiff for ComponentStorage<T> {
+    type Patch = StoragePatch<T>;
+
+    fn diff(&self, other: &Self) -> Self::Patch {
+        let mut patch = StoragePatch::default();
+        for (entity, value) in other.iter() {
+            match self.get(entity) {
+                None => patch.inserted.push((*entity, value.clone())),
+                Some(current) if current != value => patch.changed.push((*entity, value.clone())),
+                _ => (),
+            }
+        }
+        patch.removed = self
+            .entities()
+            .filter(|e| !other.contains(e))
+            .copied()
+            .collect();
+
+        patch.inserted.sort_by_key(|(e, _)| *e);
+        patch.changed.sort_by_key(|(e, _)| *e);
+        patch.removed.sort();
+        patch
+    }
+    fn apply_patch(&mut self, patch: &Self::Patch) {
+        for entity in patch.removed.iter() {
+            let _ = self.remove(*entity);
+        }
+        for (entity, value) in patch.inserted.iter().chain(patch.changed.iter()) {
+            self.__insert(*entity, value.clone());
+        }
+    }
+}
+
+/// Relations are diffed by their sources (the patch holds the target lists).
+impl<K: RelationKind> StorageDiff for Relation<K> {
+    type Patch = StoragePatch<Vec<Entity>>;
+
+    fn diff(&self, other: &Self) -> Self::Patch {
+        let mut patch = StoragePatch::default();
+        for source in other.entities() {
+            let targets = other.targets(source);
+            match self.get(source) {
+                None => patch.inserted.push((*source, targets.to_vec())),
+                Some(current) if current != targets => {
+                    patch.changed.push((*source, targets.to_vec()))
+                }
+                _ => (),
+            }
+        }
+        patch.removed = self
+            .entities()
+            .filter(|e| other.get(e).is_none())
+            .copied()
+            .collect();
+
+        patch.inserted.sort_by_key(|(e, _)| *e);
+        patch.changed.sort_by_key(|(e, _)| *e);
+        patch.removed.sort();
+        patch
+    }
+    fn apply_patch(&mut self, patch: &Self::Patch) {
+        let changed = patch.changed.iter().map(|(e, _)| e);
+        for &source in patch.removed.iter().chain(changed) {
+            for target in self.targets(&source).to_vec() {
+                self.unrelate(source, target);
+            }
+        }
+        for (source, targets) in patch.inserted.iter().chain(patch.changed.iter()) {
+            for target in targets {
+                self.__insert(*source, *target);
+            }
+        }
+    }
+}
+
+/// Changes between two world states, created by `WorldStorage::diff`.
+/// Besides the listed entity changes, the patch carries the target's
+/// recycle list, so the replayed world spawns the same entities afterwards.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct WorldPatch<P> {
+    pub spawned: Vec<Entity>,
+    pub despawned: Vec<Entity>,
+    /// New parents of the entities which parent has changed.
+    pub parents: Vec<(Entity, Option<Entity>)>,
+    /// Per field changes (the patch struct generated by the `ComponentSet`
+    /// derive).
+    pub components: P,
+    pub(crate) recycled: Vec<Entity>,
+    pub(crate) slots: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::entity::IdSize;
+    use crate::storage::relation::{Arity, OnDespawn};
+
+    fn entity(id: IdSize) -> Entity {
+        Entity { id, version: 0 }
+    }
+
+    #[test]
+    fn storage_diff() {
+        let mut a = ComponentStorage::default();
+        a.__insert(entity(0), 0);
+        a.__insert(entity(1), 1);
+        a.__insert(entity(2), 2);
+
+        let mut b = ComponentStorage::default();
+        b.__insert(entity(3), 3);
+        b.__insert(entity(2), 20);
+        b.__insert(entity(1), 1);
+
+        let patch = a.diff(&b);
+        assert_eq!(patch.inserted, vec![(entity(3), 3)]);
+        assert_eq!(patch.changed, vec![(entity(2), 20)]);
+        assert_eq!(patch.removed, vec![entity(0)]);
+
+        a.apply_patch(&patch);
+        assert!(a.diff(&b).is_empty());
+        assert_eq!(a.len(), 3);
+    }
+
+    #[test]
+    fn relation_diff() {
+        struct Likes;
+        impl RelationKind for Likes {
+            const ARITY: Arity = Arity::ManyToMany;
+            const ON_DESPAWN: OnDespawn = OnDespawn::Remove;
+        }
+        let mut a = Relation::<Likes>::default();
+        a.__insert(entity(0), entity(1));
+        a.__insert(entity(1), entity(2));
+
+        let mut b = Relation::<Likes>::default();
+        b.__insert(entity(0), entity(1));
+        b.__insert(entity(0), entity(2));
+        b.__insert(entity(2), entity(0));
+
+        let patch = a.diff(&b);
+        assert_eq!(patch.inserted, vec![(entity(2), vec![entity(0)])]);
+        assert_eq!(patch.changed, vec![(entity(0), vec![entity(1), entity(2)])]);
+        assert_eq!(patch.removed, vec![entity(1)]);
+
+        a.apply_patch(&patch);
+        assert!(a.diff(&b).is_empty());
+        assert_eq!(a.sources(&entity(2)), &[entity(0)]);
+    }
+}
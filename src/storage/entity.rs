@@ -1,7 +1,25 @@
 #[cfg(feature = "serialize")]
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 
+use super::error::WorldError;
+
+/// Width of the entity ids and versions.
+/// Defaults to `u16`, can be extended with the `id32` or `id64` features
+/// (the wider one wins if both are enabled).
+#[cfg(not(any(feature = "id32", feature = "id64")))]
 pub type IdSize = u16;
+#[cfg(all(feature = "id32", not(feature = "id64")))]
+pub type IdSize = u32;
+#[cfg(feature = "id64")]
+pub type IdSize = u64;
+
+/// Version marking a slot that can't be recycled anymore.
+const RETIRED: IdSize = IdSize::MAX;
+/// The max id is reserved, so the dense indices of the component storages
+/// never reach their tombstone value.
+const MAX_ENTITIES: usize = IdSize::MAX as usize;
 
 /// Unique world object identifier.
 #[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq, PartialOrd, Ord)]
@@ -10,6 +28,65 @@ pub struct Entity {
     pub id: IdSize,
     pub version: IdSize,
 }
+/// Formatted as `id:version`.
+impl std::fmt::Display for Entity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}", self.id, self.version)
+    }
+}
+
+/// Entities reserved by the command buffers, shared between the storage
+/// and the buffers.
+/// The reserved entities are spawned on the next modification of the
+/// storage, so the world can't hand them out again.
+#[derive(Clone, Default)]
+pub(crate) struct Reservations(Arc<Mutex<Reserved>>);
+impl Reservations {
+    /// Reserve the entity following the already reserved ones.
+    pub(crate) fn reserve(&self) -> Result<Entity, WorldError> {
+        self.lock().reserve()
+    }
+    pub(crate) fn is_shared_with(&self, other: &Reservations) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+    fn lock(&self) -> MutexGuard<'_, Reserved> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Mirror of the storage's free entities, kept in sync only while
+/// some command buffer holds the reservations.
+#[derive(Default)]
+struct Reserved {
+    synced: bool,
+    recycled: VecDeque<Entity>,
+    next_id: usize,
+    count: usize,
+}
+impl Reserved {
+    fn reserve(&mut self) -> Result<Entity, WorldError> {
+        let entity = match self.recycled.get(self.count) {
+            Some(entity) => *entity,
+            None => {
+                let id = self.next_id + self.count - self.recycled.len();
+                if id == MAX_ENTITIES {
+                    return Err(WorldError::IdExhausted);
+                }
+                Entity {
+                    id: id as IdSize,
+                    version: 0,
+                }
+            }
+        };
+        self.count += 1;
+        Ok(entity)
+    }
+    fn spawned(&mut self) {
+        if self.recycled.pop_front().is_none() {
+            self.next_id += 1;
+        }
+    }
+}
 
 /// EntityStorage responsible for spawning and despawning of the entities.
 /// Entity id's are recycled internally and versioned to avoid dead entity
@@ -31,20 +108,50 @@ pub struct EntityStorage {
     entities: Vec<Entity>,
     last_recycled: Option<IdSize>,
     first_recycled: Option<IdSize>,
+    // pending reservations belong to the original storage only
+    #[cfg_attr(feature = "serialize", serde(skip))]
+    reservations: Reservations,
+}
+/// The pending reservations are not cloned.
+impl Clone for EntityStorage {
+    fn clone(&self) -> Self {
+        Self {
+            entities: self.entities.clone(),
+            last_recycled: self.last_recycled,
+            first_recycled: self.first_recycled,
+            reservations: Reservations::default(),
+        }
+    }
 }
 impl EntityStorage {
     /// Spawn an Entity
+    /// Panics if the id space is exhausted.
     pub(crate) fn spawn(&mut self) -> Entity {
-        if let Some(entity) = self.recycle() {
-            return entity;
+        self.try_spawn().expect("Entity id space exhausted!")
+    }
+    /// Spawn an Entity, or return an error if the id space is exhausted.
+    pub(crate) fn try_spawn(&mut self) -> Result<Entity, WorldError> {
+        self.flush();
+        let entity = self.spawn_unreserved()?;
+        if let Some(reservations) = self.shared_reservations() {
+            reservations.lock().spawned();
         }
-        self.spawn_new()
+        Ok(entity)
     }
     /// Despawn Entity from the storage
+    /// Invalid entities are ignored.
     pub(crate) fn despawn(&mut self, entity: Entity) {
-        if self.entities[entity.id as usize].version != entity.version {
-            // already despawned!
-            return;
+        let _ = self.try_despawn(entity);
+    }
+    /// Despawn Entity from the storage, or return an error if the entity
+    /// is not valid.
+    pub(crate) fn try_despawn(&mut self, entity: Entity) -> Result<(), WorldError> {
+        self.flush();
+        self.validate(&entity)?;
+        if entity.version >= RETIRED - 1 {
+            // the version would wrap, so the slot can't be safely reused
+            self.entities[entity.id as usize].version = RETIRED;
+            return Ok(());
         }
         self.entities[entity.id as usize].version += 1;
         if let Some(last) = self.last_recycled {
@@ -56,6 +163,13 @@ impl EntityStorage {
         }
         // now this one is the prev_recycled
         self.last_recycled = Some(entity.id);
+        if let Some(reservations) = self.shared_reservations() {
+            reservations.lock().recycled.push_back(Entity {
+                id: entity.id,
+                version: entity.version + 1,
+            });
+        }
+        Ok(())
     }
     /// Validates the given entity
     pub(crate) fn is_valid(&self, entity: &Entity) -> bool {
@@ -63,15 +177,149 @@ impl EntityStorage {
             return false;
         };
         existing.version == entity.version
+            && entity.version != RETIRED
             && existing.id == entity.id
             && self.last_recycled != Some(entity.id)
             && self.first_recycled != Some(entity.id)
     }
+    /// Validates the given entity, reporting the reason of a failure
+    pub(crate) fn validate(&self, entity: &Entity) -> Result<(), WorldError> {
+        let Some(existing) = self.entities.get(entity.id as usize) else {
+            return Err(WorldError::UnknownId(*entity));
+        };
+        if self.is_valid(entity) {
+            return Ok(());
+        }
+        if entity.version > existing.version {
+            return Err(WorldError::UnknownId(*entity));
+        }
+        let current = Entity {
+            id: entity.id,
+            version: existing.version,
+        };
+        match self.is_valid(&current) {
+            true => Err(WorldError::StaleVersion(*entity)),
+            false => Err(WorldError::DeadEntity(*entity)),
+        }
+    }
     /// Iterate through valid entities
     pub(crate) fn all(&self) -> impl Iterator<Item = &Entity> + use<'_> {
         self.entities.iter().filter(|e| self.is_valid(e))
     }
 
+    /// Reservations for a new command buffer.
+    /// The entities are reserved in the order of the subsequent spawns
+    /// (recycled entities first, followed by the fresh ones).
+    pub(crate) fn reservations(&self) -> Reservations {
+        let mut reserved = self.reservations.lock();
+        if !reserved.synced {
+            *reserved = Reserved {
+                synced: true,
+                recycled: self.recycled(),
+                next_id: self.entities.len(),
+                count: 0,
+            };
+        }
+        self.reservations.clone()
+    }
+    /// Spawn the entities reserved by the command buffers so far.
+    pub(crate) fn flush(&mut self) {
+        let Some(reservations) = self.shared_reservations() else {
+            return;
+        };
+        let mut reserved = reservations.lock();
+        for _ in 0..std::mem::take(&mut reserved.count) {
+            // the reservations are checked against the id exhaustion
+            // and kept in sync with the free entities
+            let _ = self.spawn_unreserved();
+            reserved.spawned();
+        }
+    }
+    pub(crate) fn owns(&self, reservations: &Reservations) -> bool {
+        self.reservations.is_shared_with(reservations)
+    }
+    /// Number of the allocated ids (alive, recycled and retired).
+    pub(crate) fn slots(&self) -> usize {
+        self.entities.len()
+    }
+    /// Rebuild the state of another storage from it's differences with
+    /// this one: the spawned and despawned entities, the other's recycle
+    /// list and slot count. Slots that end up neither alive nor recycled
+    /// are retired.
+    pub(crate) fn apply_changes(
+        &mut self,
+        spawned: &[Entity],
+        despawned: &[Entity],
+        recycled: &[Entity],
+        slots: usize,
+    ) {
+        // the pending reservations don't match the new state,
+        // so the command buffers fall back to remapping
+        self.reservations = Reservations::default();
+        let retired = |id: IdSize| Entity {
+            id,
+            version: RETIRED,
+        };
+        for entity in self.recycled() {
+            self.entities[entity.id as usize] = retired(entity.id);
+        }
+        while self.entities.len() < slots {
+            self.entities.push(retired(self.entities.len() as IdSize));
+        }
+        for entity in despawned {
+            self.entities[entity.id as usize] = retired(entity.id);
+        }
+        for entity in spawned {
+            self.entities[entity.id as usize] = *entity;
+        }
+        for (i, entity) in recycled.iter().enumerate() {
+            // the next recycled index is stored in the id
+            let next = recycled.get(i + 1).map_or(entity.id, |e| e.id);
+            self.entities[entity.id as usize] = Entity {
+                id: next,
+                version: entity.version,
+            };
+        }
+        self.first_recycled = recycled.first().map(|e| e.id);
+        self.last_recycled = recycled.last().map(|e| e.id);
+    }
+
+    /// Reservations used by the alive command buffers, if there are any.
+    /// Otherwise the stale reservations are dropped.
+    fn shared_reservations(&mut self) -> Option<Reservations> {
+        if let Some(reserved) = Arc::get_mut(&mut self.reservations.0) {
+            *reserved.get_mut().unwrap_or_else(PoisonError::into_inner) = Reserved::default();
+            return None;
+        }
+        Some(self.reservations.clone())
+    }
+    pub(crate) fn recycled(&self) -> VecDeque<Entity> {
+        let mut recycled = VecDeque::new();
+        let mut next = self.first_recycled;
+        while let Some(id) = next {
+            let entity = self.entities[id as usize];
+            recycled.push_back(Entity {
+                id,
+                version: entity.version,
+            });
+            next = match self.last_recycled == Some(id) {
+                true => None,
+                // the next recycled index is temporarily stored in the id
+                false => Some(entity.id),
+            };
+        }
+        recycled
+    }
+    fn spawn_unreserved(&mut self) -> Result<Entity, WorldError> {
+        if let Some(entity) = self.recycle() {
+            return Ok(entity);
+        }
+        if self.entities.len() == MAX_ENTITIES {
+            return Err(WorldError::IdExhausted);
+        }
+        Ok(self.spawn_new())
+    }
+
     /// Spawns a fresh entity, with version 0
     fn spawn_new(&mut self) -> Entity {
         let id = self.entities.len();
@@ -217,6 +465,144 @@ mod tests {
         assert!(!storage.is_valid(&entity));
     }
 
+    #[test]
+    fn retire_wrapping_version() {
+        let mut storage = EntityStorage::default();
+        let a = storage.spawn();
+        let _ = storage.spawn();
+        storage.entities[a.id as usize].version = RETIRED - 1;
+        let a = Entity {
+            id: a.id,
+            version: RETIRED - 1,
+        };
+        assert!(storage.is_valid(&a));
+
+        storage.despawn(a);
+        assert!(!storage.is_valid(&a));
+        assert!(!storage.is_valid(&Entity {
+            id: a.id,
+            version: RETIRED
+        }));
+        assert_eq!(storage.all().count(), 1);
+
+        // the slot is not recycled
+        assert_eq!(storage.spawn().id, 2);
+    }
+
+    #[cfg(not(any(feature = "id32", feature = "id64")))]
+    #[test]
+    fn try_spawn_exhausted() {
+        let mut storage = EntityStorage::default();
+        for _ in 0..MAX_ENTITIES {
+            assert!(storage.try_spawn().is_ok());
+        }
+        assert_eq!(storage.try_spawn(), Err(WorldError::IdExhausted));
+
+        // recycled ids are still available
+        let entity = Entity { id: 5, version: 0 };
+        storage.despawn(entity);
+        assert_eq!(storage.try_spawn().unwrap().id, 5);
+        assert_eq!(storage.try_spawn(), Err(WorldError::IdExhausted));
+    }
+
+    #[test]
+    fn validate() {
+        let mut storage = EntityStorage::default();
+        let a = storage.spawn();
+        let b = storage.spawn();
+        assert_eq!(storage.validate(&a), Ok(()));
+
+        storage.despawn(a);
+        assert_eq!(storage.validate(&a), Err(WorldError::DeadEntity(a)));
+        let c = storage.spawn();
+        assert_eq!(storage.validate(&a), Err(WorldError::StaleVersion(a)));
+        assert_eq!(storage.validate(&c), Ok(()));
+
+        let unknown = Entity { id: 10, version: 0 };
+        assert_eq!(
+            storage.validate(&unknown),
+            Err(WorldError::UnknownId(unknown))
+        );
+        let unknown = Entity {
+            id: b.id,
+            version: 3,
+        };
+        assert_eq!(
+            storage.validate(&unknown),
+            Err(WorldError::UnknownId(unknown))
+        );
+    }
+
+    #[test]
+    fn try_despawn() {
+        let mut storage = EntityStorage::default();
+        let a = storage.spawn();
+        assert_eq!(storage.try_despawn(a), Ok(()));
+        assert_eq!(storage.try_despawn(a), Err(WorldError::DeadEntity(a)));
+
+        // does not panic on never spawned entities
+        let unknown = Entity { id: 10, version: 0 };
+        storage.despawn(unknown);
+        assert_eq!(
+            storage.try_despawn(unknown),
+            Err(WorldError::UnknownId(unknown))
+        );
+        // the recycle list is intact
+        assert_eq!(storage.spawn().id, a.id);
+        assert_eq!(storage.spawn().id, 1);
+    }
+
+    #[test]
+    fn reserve() {
+        let mut storage = EntityStorage::default();
+        for _ in 0..10 {
+            storage.spawn();
+        }
+        storage.despawn(Entity { id: 7, version: 0 });
+        storage.despawn(Entity { id: 2, version: 0 });
+
+        let reservations = storage.reservations();
+        let reserved = (0..3)
+            .map(|_| reservations.reserve().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reserved,
+            vec![
+                Entity { id: 7, version: 1 },
+                Entity { id: 2, version: 1 },
+                Entity { id: 10, version: 0 }
+            ]
+        );
+        assert!(!storage.is_valid(&reserved[0]));
+
+        // the reserved entities are spawned before the next one
+        assert_eq!(storage.spawn().id, 11);
+        assert!(reserved.iter().all(|e| storage.is_valid(e)));
+        storage.despawn(reserved[1]);
+        assert_eq!(reservations.reserve(), Ok(Entity { id: 2, version: 2 }));
+        assert_eq!(reservations.reserve(), Ok(Entity { id: 12, version: 0 }));
+    }
+
+    #[cfg(not(any(feature = "id32", feature = "id64")))]
+    #[test]
+    fn reserve_exhausted() {
+        let mut storage = EntityStorage::default();
+        for _ in 0..MAX_ENTITIES - 1 {
+            storage.spawn();
+        }
+        storage.despawn(Entity { id: 5, version: 0 });
+        let reservations = storage.reservations();
+        assert_eq!(reservations.reserve().unwrap().id, 5);
+        assert_eq!(
+            reservations.reserve().unwrap().id as usize,
+            MAX_ENTITIES - 1
+        );
+        assert_eq!(reservations.reserve(), Err(WorldError::IdExhausted));
+
+        storage.flush();
+        assert_eq!(storage.try_spawn(), Err(WorldError::IdExhausted));
+    }
+
     #[test]
     fn all() {
         let mut storage = EntityStorage::default();
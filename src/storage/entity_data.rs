@@ -0,0 +1,291 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::components::{ComponentSet, JsonValue};
+use super::entity::Entity;
+use super::error::{ComponentError, EntityDataError};
+use super::world::WorldStorage;
+
+/// Single entity with all it's serializable components,
+/// created by `WorldStorage::export_entity`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct EntityData {
+    /// The exported entity. It's references in the components are
+    /// replaced with the new entity on import.
+    pub entity: Entity,
+    /// Parent in the hierarchy (the children are not exported).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<Entity>,
+    /// Component values keyed by the `ComponentSet` field names.
+    pub components: BTreeMap<String, JsonValue>,
+}
+
+impl<C: ComponentSet, R: Default> WorldStorage<C, R> {
+    /// Serialize the entity's `#[component(serialize)]` components
+    /// (the other ones are skipped).
+    /// ```
+    /// use wunderkammer::prelude::*;
+    ///
+    /// #[derive(ComponentSet, Default)]
+    /// struct Components {
+    ///     #[component(serialize)]
+    ///     pub health: ComponentStorage<u32>,
+    /// }
+    ///
+    /// let mut world = WorldStorage::<Components, ()>::default();
+    /// let entity = world.spawn();
+    /// insert!(world, health, entity, 5);
+    ///
+    /// let data = world.export_entity(entity).unwrap();
+    /// let json = serde_json::to_string(&data).unwrap();
+    ///
+    /// let mut other = WorldStorage::<Components, ()>::default();
+    /// let data = serde_json::from_str(&json).unwrap();
+    /// let imported = other.import_entity(&data).unwrap();
+    /// assert_eq!(other.cmps.health.get(&imported), Some(&5));
+    /// ```
+    pub fn export_entity(&self, entity: Entity) -> Result<EntityData, EntityDataError> {
+        self.validate(&entity)?;
+        let mut components = BTreeMap::new();
+        for component in self.cmps.component_names(entity) {
+            match self.cmps.get_str_serialized(component, entity) {
+                Ok(Some(value)) => {
+                    components.insert(component.to_string(), value);
+                }
+                Ok(None) | Err(ComponentError::NotSerializable(_)) => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(EntityData {
+            entity,
+            parent: self.parent(&entity),
+            components,
+        })
+    }
+    /// Spawn a new entity from the exported data.
+    /// References to the exported entity (relation targets and
+    /// `#[component(map_entities)]` values) are mapped to the new one,
+    /// other entities are kept as they are and have to be valid.
+    /// The parent is set if it's still valid.
+    /// If any of the components can't be inserted, the entity is despawned
+    /// and the error is returned (the other entities are left untouched).
+    /// The `Arity::OneToMany` targets that already have a source are skipped,
+    /// as they would have been taken away from it.
+    pub fn import_entity(&mut self, data: &EntityData) -> Result<Entity, EntityDataError>
+    where
+        C: Default,
+    {
+        self.import_entity_with(data, &mut Some)
+    }
+    /// Same as `import_entity`, but the other referenced entities
+    /// (and the parent) are mapped by the caller, e.g. when importing
+    /// into a different world.
+    /// References that are mapped to `None` or to an invalid entity fail
+    /// the import with `EntityDataError::UnmappedEntity`, such parent is
+    /// skipped.
+    pub fn import_entity_with(
+        &mut self,
+        data: &EntityData,
+        mapper: &mut dyn FnMut(Entity) -> Option<Entity>,
+    ) -> Result<Entity, EntityDataError>
+    where
+        C: Default,
+    {
+        let entity = self.try_spawn()?;
+        let mut unmapped = None;
+        let entities = &self.entities;
+        let mut map = |e: Entity| {
+            if e == data.entity {
+                return entity;
+            }
+            match mapper(e).filter(|m| entities.is_valid(m)) {
+                Some(mapped) => mapped,
+                None => {
+                    unmapped.get_or_insert(e);
+                    e
+                }
+            }
+        };
+        // the components are staged first, so a failed import
+        // doesn't modify the relations of the existing entities
+        let mut staged = C::default();
+        let result = data.components.iter().try_for_each(|(component, value)| {
+            staged.insert_str_serialized_mapped(component, entity, value, &mut map)
+        });
+        let result = match (result, unmapped) {
+            (Err(e), _) => Err(e.into()),
+            (Ok(_), Some(e)) => Err(EntityDataError::UnmappedEntity(e)),
+            (Ok(_), None) => Ok(()),
+        };
+        if let Err(e) = result {
+            self.despawn(entity);
+            return Err(e);
+        }
+        self.cmps.move_entity(&mut staged, entity, &mut Some);
+        if let Some(parent) = data.parent.and_then(mapper).filter(|p| self.is_valid(p)) {
+            self.set_parent(entity, parent);
+        }
+        Ok(entity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use crate::storage::entity::IdSize;
+    use serde::{Deserialize, Serialize};
+
+    fn entity(id: IdSize) -> Entity {
+        Entity { id, version: 0 }
+    }
+
+    struct Likes;
+    impl RelationKind for Likes {
+        const ARITY: Arity = Arity::ManyToMany;
+        const ON_DESPAWN: OnDespawn = OnDespawn::Remove;
+    }
+    struct Owns;
+    impl RelationKind for Owns {
+        const ARITY: Arity = Arity::OneToMany;
+        const ON_DESPAWN: OnDespawn = OnDespawn::Remove;
+    }
+    #[derive(Clone, Debug, Deserialize, Hash, MapEntities, PartialEq, Serialize)]
+    struct Memory {
+        #[entities]
+        seen: Vec<Entity>,
+    }
+    struct Handle;
+
+    #[derive(ComponentSet, Default)]
+    struct C {
+        #[component(serialize)]
+        pub health: ComponentStorage<u32>,
+        #[component(map_entities, serialize)]
+        pub memory: ComponentStorage<Memory>,
+        #[component(serialize)]
+        pub likes: Relation<Likes>,
+        #[component(serialize)]
+        pub carries: Relation<Owns>,
+        #[component(no_clone, no_hash, no_debug)]
+        pub handle: ComponentStorage<Handle>,
+    }
+
+    #[test]
+    fn export_import() {
+        let mut w = WorldStorage::<C, ()>::default();
+        let a = w.spawn();
+        let b = w.spawn();
+        insert!(w, health, b, 5);
+        insert!(w, handle, b, Handle);
+        insert!(w, memory, b, Memory { seen: vec![a, b] });
+        insert!(w, likes, b, a);
+        insert!(w, likes, b, b);
+
+        w.set_parent(b, a);
+
+        let data = w.export_entity(b).unwrap();
+        assert_eq!(data.entity, b);
+        assert_eq!(data.parent, Some(a));
+        assert_eq!(
+            data.components.keys().collect::<Vec<_>>(),
+            vec!["health", "likes", "memory"]
+        );
+
+        let json = serde_json::to_string(&data).unwrap();
+        let data: EntityData = serde_json::from_str(&json).unwrap();
+        let c = w.import_entity(&data).unwrap();
+        assert_eq!(c, entity(2));
+        assert_eq!(w.cmps.health.get(&c), Some(&5));
+        assert!(w.cmps.handle.get(&c).is_none());
+        // self references are mapped, others are kept
+        assert_eq!(w.cmps.memory.get(&c).unwrap().seen, vec![a, c]);
+        assert_eq!(w.cmps.likes.targets(&c), &[a, c]);
+        assert_eq!(w.cmps.likes.targets(&b), &[a, b]);
+        assert_eq!(w.parent(&c), Some(a));
+    }
+
+    #[test]
+    fn import_mapped() {
+        let mut w = WorldStorage::<C, ()>::default();
+        let a = w.spawn();
+        let b = w.spawn();
+        insert!(w, memory, b, Memory { seen: vec![a] });
+        w.set_parent(b, a);
+        let data = w.export_entity(b).unwrap();
+
+        let mut other = WorldStorage::<C, ()>::default();
+        let x = other.spawn();
+        other.despawn(x);
+        assert_eq!(
+            other.import_entity(&data),
+            Err(EntityDataError::UnmappedEntity(a))
+        );
+        assert_eq!(other.entities().count(), 0);
+        assert_eq!(
+            other.import_entity_with(&data, &mut |_| None),
+            Err(EntityDataError::UnmappedEntity(a))
+        );
+
+        let y = other.spawn();
+        let c = other
+            .import_entity_with(&data, &mut |e| (e == a).then_some(y))
+            .unwrap();
+        assert_eq!(other.cmps.memory.get(&c).unwrap().seen, vec![y]);
+        assert_eq!(other.parent(&c), Some(y));
+    }
+
+    #[test]
+    fn import_owned_target() {
+        let mut w = WorldStorage::<C, ()>::default();
+        let a = w.spawn();
+        let item = w.spawn();
+        insert!(w, carries, a, item);
+        let mut data = w.export_entity(a).unwrap();
+
+        // the relation is inserted before the failing component,
+        // but the failed import doesn't take the item away
+        data.components
+            .insert("health".to_string(), serde_json::json!("full"));
+        assert!(matches!(
+            w.import_entity(&data),
+            Err(EntityDataError::Component(
+                ComponentError::InvalidValue { .. }
+            ))
+        ));
+        assert_eq!(w.entities().count(), 2);
+        assert_eq!(w.cmps.carries.targets(&a), &[item]);
+
+        // the owned target is skipped
+        data.components
+            .insert("health".to_string(), serde_json::json!(5));
+        let b = w.import_entity(&data).unwrap();
+        assert_eq!(w.cmps.health.get(&b), Some(&5));
+        assert_eq!(w.cmps.carries.targets(&a), &[item]);
+        assert!(w.cmps.carries.targets(&b).is_empty());
+        assert_eq!(w.cmps.carries.sources(&item), &[a]);
+    }
+
+    #[test]
+    fn errors() {
+        let mut w = WorldStorage::<C, ()>::default();
+        let a = w.spawn();
+        w.despawn(a);
+        assert_eq!(
+            w.export_entity(a),
+            Err(EntityDataError::World(WorldError::DeadEntity(a)))
+        );
+
+        let mut data = EntityData::default();
+        data.components
+            .insert("health".to_string(), serde_json::json!(3));
+        data.components
+            .insert("mana".to_string(), serde_json::json!(3));
+        assert_eq!(
+            w.import_entity(&data),
+            Err(EntityDataError::Component(
+                ComponentError::UnknownComponent("mana".to_string())
+            ))
+        );
+        assert_eq!(w.entities().count(), 0);
+    }
+}
@@ -0,0 +1,171 @@
+use super::components::ComponentStorage;
+use super::entity::Entity;
+
+/// A view into a single entity's slot in the component storage,
+/// which may be either occupied or vacant.
+/// Constructed by `WorldStorage::entry` or `ComponentStorage::entry`.
+pub enum Entry<'a, T> {
+    Occupied(OccupiedEntry<'a, T>),
+    Vacant(VacantEntry<'a, T>),
+}
+impl<'a, T> Entry<'a, T> {
+    pub fn entity(&self) -> Entity {
+        match self {
+            Entry::Occupied(e) => e.entity,
+            Entry::Vacant(e) => e.entity,
+        }
+    }
+    /// Insert the value if the component is missing.
+    /// Returns a mutable reference to the component.
+    pub fn or_insert(self, value: T) -> &'a mut T {
+        self.or_insert_with(|| value)
+    }
+    pub fn or_insert_with(self, f: impl FnOnce() -> T) -> &'a mut T {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(f()),
+        }
+    }
+    pub fn or_default(self) -> &'a mut T
+    where
+        T: Default,
+    {
+        self.or_insert_with(T::default)
+    }
+    /// Modify the component in place, if it exists.
+    pub fn and_modify(self, f: impl FnOnce(&mut T)) -> Self {
+        match self {
+            Entry::Occupied(mut e) => {
+                f(e.get_mut());
+                Entry::Occupied(e)
+            }
+            Entry::Vacant(e) => Entry::Vacant(e),
+        }
+    }
+    /// Remove the component (if exists) and return it.
+    pub fn remove(self) -> Option<T> {
+        match self {
+            Entry::Occupied(e) => Some(e.remove()),
+            Entry::Vacant(_) => None,
+        }
+    }
+}
+
+pub struct OccupiedEntry<'a, T> {
+    storage: &'a mut ComponentStorage<T>,
+    entity: Entity,
+}
+impl<'a, T> OccupiedEntry<'a, T> {
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+    pub fn get(&self) -> &T {
+        // the entry is only created for existing components
+        self.storage.get(&self.entity).unwrap()
+    }
+    pub fn get_mut(&mut self) -> &mut T {
+        self.storage.get_mut(&self.entity).unwrap()
+    }
+    pub fn into_mut(self) -> &'a mut T {
+        self.storage.get_mut(&self.entity).unwrap()
+    }
+    /// Replace the component, returning the previous value.
+    pub fn insert(&mut self, value: T) -> T {
+        std::mem::replace(self.get_mut(), value)
+    }
+    pub fn remove(self) -> T {
+        self.storage.remove(self.entity).unwrap()
+    }
+}
+
+pub struct VacantEntry<'a, T> {
+    storage: &'a mut ComponentStorage<T>,
+    entity: Entity,
+}
+impl<'a, T> VacantEntry<'a, T> {
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+    pub fn insert(self, value: T) -> &'a mut T {
+        self.storage.__insert(self.entity, value);
+        self.storage.get_mut(&self.entity).unwrap()
+    }
+}
+
+impl<T> ComponentStorage<T> {
+    // Get the entity's entry for in-place manipulation.
+    // Since it cannot validate the entity,
+    // it is recommended to use `WorldStorage::entry` that calls it internally.
+    pub fn entry(&mut self, entity: Entity) -> Entry<'_, T> {
+        match self.contains(&entity) {
+            true => Entry::Occupied(OccupiedEntry {
+                storage: self,
+                entity,
+            }),
+            false => Entry::Vacant(VacantEntry {
+                storage: self,
+                entity,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn or_insert() {
+        let mut storage = ComponentStorage::default();
+        let entity = Entity { id: 0, version: 0 };
+
+        *storage.entry(entity).or_insert(0) += 1;
+        *storage.entry(entity).or_insert(0) += 1;
+        assert_eq!(storage.get(&entity), Some(&2));
+
+        let other = Entity { id: 1, version: 0 };
+        assert_eq!(*storage.entry(other).or_insert_with(|| 5), 5);
+        assert_eq!(*storage.entry(other).or_default(), 5);
+    }
+
+    #[test]
+    fn and_modify() {
+        let mut storage = ComponentStorage::default();
+        let entity = Entity { id: 0, version: 0 };
+
+        storage.entry(entity).and_modify(|v| *v += 1).or_insert(10);
+        assert_eq!(storage.get(&entity), Some(&10));
+        storage.entry(entity).and_modify(|v| *v += 1).or_insert(10);
+        assert_eq!(storage.get(&entity), Some(&11));
+    }
+
+    #[test]
+    fn occupied_and_vacant() {
+        let mut storage = ComponentStorage::default();
+        let entity = Entity { id: 3, version: 0 };
+
+        let Entry::Vacant(vacant) = storage.entry(entity) else {
+            panic!("Entry should be vacant!");
+        };
+        assert_eq!(vacant.entity(), entity);
+        *vacant.insert("A") = "B";
+
+        let Entry::Occupied(mut occupied) = storage.entry(entity) else {
+            panic!("Entry should be occupied!");
+        };
+        assert_eq!(occupied.get(), &"B");
+        assert_eq!(occupied.insert("C"), "B");
+        assert_eq!(occupied.remove(), "C");
+        assert!(storage.is_empty());
+    }
+
+    #[test]
+    fn remove() {
+        let mut storage = ComponentStorage::default();
+        let entity = Entity { id: 0, version: 0 };
+        storage.__insert(entity, 1);
+
+        assert_eq!(storage.entry(entity).remove(), Some(1));
+        assert_eq!(storage.entry(entity).remove(), None);
+    }
+}
@@ -0,0 +1,206 @@
+use std::error::Error;
+use std::fmt;
+
+use super::entity::Entity;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WorldError {
+    /// The entity has been despawned (and it's slot not reused yet).
+    DeadEntity(Entity),
+    /// The entity has never been spawned.
+    UnknownId(Entity),
+    /// The entity has been despawned and it's id recycled.
+    StaleVersion(Entity),
+    /// All the entity ids are in use (or retired).
+    IdExhausted,
+    /// The entity does not have the requested component.
+    MissingComponent(Entity),
+}
+
+impl fmt::Display for WorldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WorldError::DeadEntity(e) => write!(f, "Entity {} is dead", e),
+            WorldError::UnknownId(e) => write!(f, "Entity {} has never been spawned", e),
+            WorldError::StaleVersion(e) => write!(f, "Entity {} has a stale version", e),
+            WorldError::IdExhausted => write!(f, "Entity id space exhausted"),
+            WorldError::MissingComponent(e) => {
+                write!(f, "Entity {} does not have the component", e)
+            }
+        }
+    }
+}
+
+impl Error for WorldError {}
+
+/// Errors of the string keyed component access (`ComponentSet::*_str` methods).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ComponentError {
+    /// The component set has no field of that name.
+    UnknownComponent(String),
+    /// The component is not marked with `#[component(serialize)]`.
+    NotSerializable(String),
+    /// The value can't be converted to or from the component type.
+    InvalidValue { component: String, message: String },
+}
+
+impl fmt::Display for ComponentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ComponentError::UnknownComponent(c) => write!(f, "Unknown component `{}`", c),
+            ComponentError::NotSerializable(c) => {
+                write!(f, "Component `{}` is not serializable", c)
+            }
+            ComponentError::InvalidValue { component, message } => {
+                write!(f, "Invalid value of component `{}`: {}", component, message)
+            }
+        }
+    }
+}
+
+impl Error for ComponentError {}
+
+/// Errors of the developer console commands.
+#[cfg(feature = "serialize")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ConsoleError {
+    /// The command is not recognized.
+    UnknownCommand(String),
+    /// The command lacks the named argument.
+    MissingArgument(&'static str),
+    /// The command got more arguments than it takes.
+    UnexpectedArgument(String),
+    /// The argument is not an `id:version` pair.
+    InvalidEntity(String),
+    /// The argument is not a valid JSON value.
+    InvalidJson(String),
+    World(WorldError),
+    Component(ComponentError),
+}
+
+#[cfg(feature = "serialize")]
+impl fmt::Display for ConsoleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConsoleError::UnknownCommand(c) => write!(f, "Unknown command `{}`", c),
+            ConsoleError::MissingArgument(a) => write!(f, "Missing argument: {}", a),
+            ConsoleError::UnexpectedArgument(a) => write!(f, "Unexpected argument `{}`", a),
+            ConsoleError::InvalidEntity(e) => {
+                write!(f, "Invalid entity `{}`, expected `id:version`", e)
+            }
+            ConsoleError::InvalidJson(v) => write!(f, "Invalid JSON value `{}`", v),
+            ConsoleError::World(e) => e.fmt(f),
+            ConsoleError::Component(e) => e.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "serialize")]
+impl Error for ConsoleError {}
+
+#[cfg(feature = "serialize")]
+impl From<WorldError> for ConsoleError {
+    fn from(value: WorldError) -> Self {
+        ConsoleError::World(value)
+    }
+}
+
+#[cfg(feature = "serialize")]
+impl From<ComponentError> for ConsoleError {
+    fn from(value: ComponentError) -> Self {
+        ConsoleError::Component(value)
+    }
+}
+
+/// Errors of the prefab loading and spawning.
+#[cfg(feature = "serialize")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PrefabError {
+    /// The prefab data can't be parsed.
+    Parse(String),
+    /// There is no prefab of that name (e.g. a missing parent).
+    UnknownPrefab(String),
+    /// The prefab (indirectly) inherits from itself.
+    InheritanceCycle(String),
+    /// The component value, as defined by the named prefab, can't be inserted.
+    InvalidComponent {
+        prefab: String,
+        component: String,
+        value: String,
+        error: ComponentError,
+    },
+    World(WorldError),
+}
+
+#[cfg(feature = "serialize")]
+impl fmt::Display for PrefabError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrefabError::Parse(e) => write!(f, "Can't parse the prefabs: {}", e),
+            PrefabError::UnknownPrefab(p) => write!(f, "Unknown prefab `{}`", p),
+            PrefabError::InheritanceCycle(p) => write!(f, "Prefab `{}` inherits from itself", p),
+            PrefabError::InvalidComponent {
+                prefab,
+                component,
+                value,
+                error,
+            } => write!(
+                f,
+                "Prefab `{}` has invalid component `{}` value `{}`: {}",
+                prefab, component, value, error
+            ),
+            PrefabError::World(e) => e.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "serialize")]
+impl Error for PrefabError {}
+
+#[cfg(feature = "serialize")]
+impl From<WorldError> for PrefabError {
+    fn from(value: WorldError) -> Self {
+        PrefabError::World(value)
+    }
+}
+
+/// Errors of the single entity export and import.
+#[cfg(feature = "serialize")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EntityDataError {
+    World(WorldError),
+    Component(ComponentError),
+    /// A component refers to an entity that can't be mapped
+    /// to a valid one.
+    UnmappedEntity(Entity),
+}
+
+#[cfg(feature = "serialize")]
+impl fmt::Display for EntityDataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EntityDataError::World(e) => e.fmt(f),
+            EntityDataError::Component(e) => e.fmt(f),
+            EntityDataError::UnmappedEntity(e) => {
+                write!(f, "Referenced entity {} can't be mapped", e)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serialize")]
+impl Error for EntityDataError {}
+
+#[cfg(feature = "serialize")]
+impl From<WorldError> for EntityDataError {
+    fn from(value: WorldError) -> Self {
+        EntityDataError::World(value)
+    }
+}
+
+#[cfg(feature = "serialize")]
+impl From<ComponentError> for EntityDataError {
+    fn from(value: ComponentError) -> Self {
+        EntityDataError::Component(value)
+    }
+}
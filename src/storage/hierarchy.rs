@@ -0,0 +1,146 @@
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+
+use super::entity::Entity;
+
+/// Parent / child relations between the entities.
+/// Both directions are indexed by the entity id and kept consistent.
+/// Validity of the entities is checked by the `WorldStorage`.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct Hierarchy {
+    parents: Vec<Option<Entity>>,
+    children: Vec<Vec<Entity>>,
+}
+impl Hierarchy {
+    pub(crate) fn parent(&self, entity: &Entity) -> Option<Entity> {
+        *self.parents.get(entity.id as usize)?
+    }
+    pub(crate) fn children(&self, entity: &Entity) -> &[Entity] {
+        self.children
+            .get(entity.id as usize)
+            .map(|a| a.as_slice())
+            .unwrap_or_default()
+    }
+    /// Iterate from the direct parent up to the root.
+    pub(crate) fn ancestors(&self, entity: &Entity) -> impl Iterator<Item = Entity> + use<'_> {
+        std::iter::successors(self.parent(entity), |e| self.parent(e))
+    }
+    /// Collect all the descendants (depth first).
+    pub(crate) fn descendants(&self, entity: &Entity) -> Vec<Entity> {
+        let mut result = Vec::new();
+        let mut stack = self.children(entity).to_vec();
+        while let Some(e) = stack.pop() {
+            stack.extend(self.children(&e));
+            result.push(e);
+        }
+        result
+    }
+    /// Attach the child to a new parent (detaching it from the previous one).
+    /// Returns false if the relation would create a cycle.
+    pub(crate) fn set_parent(&mut self, child: Entity, parent: Entity) -> bool {
+        if child == parent || self.ancestors(&parent).any(|a| a == child) {
+            return false;
+        }
+        self.remove_parent(child);
+
+        let (child_idx, parent_idx) = (child.id as usize, parent.id as usize);
+        if child_idx >= self.parents.len() {
+            self.parents.resize(child_idx + 1, None);
+        }
+        if parent_idx >= self.children.len() {
+            self.children.resize(parent_idx + 1, Vec::new());
+        }
+        self.parents[child_idx] = Some(parent);
+        self.children[parent_idx].push(child);
+        true
+    }
+    /// Detach the child from it's parent. Returns the previous parent.
+    pub(crate) fn remove_parent(&mut self, child: Entity) -> Option<Entity> {
+        let parent = self.parents.get_mut(child.id as usize)?.take()?;
+        self.children[parent.id as usize].retain(|&c| c != child);
+        Some(parent)
+    }
+    /// Remove all the relations of the entity.
+    /// It's children become roots.
+    pub(crate) fn remove(&mut self, entity: Entity) {
+        self.remove_parent(entity);
+        let Some(children) = self.children.get_mut(entity.id as usize) else {
+            return;
+        };
+        for child in std::mem::take(children) {
+            self.parents[child.id as usize] = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::entity::IdSize;
+
+    fn entity(id: IdSize) -> Entity {
+        Entity { id, version: 0 }
+    }
+
+    #[test]
+    fn set_parent() {
+        let mut h = Hierarchy::default();
+        assert!(h.set_parent(entity(1), entity(0)));
+        assert!(h.set_parent(entity(2), entity(0)));
+
+        assert_eq!(h.parent(&entity(1)), Some(entity(0)));
+        assert_eq!(h.children(&entity(0)), &[entity(1), entity(2)]);
+        assert!(h.children(&entity(1)).is_empty());
+    }
+
+    #[test]
+    fn reparent() {
+        let mut h = Hierarchy::default();
+        h.set_parent(entity(2), entity(0));
+        h.set_parent(entity(2), entity(1));
+
+        assert_eq!(h.parent(&entity(2)), Some(entity(1)));
+        assert!(h.children(&entity(0)).is_empty());
+        assert_eq!(h.children(&entity(1)), &[entity(2)]);
+    }
+
+    #[test]
+    fn reject_cycles() {
+        let mut h = Hierarchy::default();
+        h.set_parent(entity(1), entity(0));
+        h.set_parent(entity(2), entity(1));
+
+        assert!(!h.set_parent(entity(0), entity(2)));
+        assert!(!h.set_parent(entity(0), entity(0)));
+        assert_eq!(h.parent(&entity(0)), None);
+    }
+
+    #[test]
+    fn ancestors_and_descendants() {
+        let mut h = Hierarchy::default();
+        h.set_parent(entity(1), entity(0));
+        h.set_parent(entity(2), entity(1));
+        h.set_parent(entity(3), entity(1));
+
+        assert_eq!(
+            h.ancestors(&entity(3)).collect::<Vec<_>>(),
+            vec![entity(1), entity(0)]
+        );
+        let mut descendants = h.descendants(&entity(0));
+        descendants.sort();
+        assert_eq!(descendants, vec![entity(1), entity(2), entity(3)]);
+    }
+
+    #[test]
+    fn remove() {
+        let mut h = Hierarchy::default();
+        h.set_parent(entity(1), entity(0));
+        h.set_parent(entity(2), entity(1));
+        h.remove(entity(1));
+
+        assert!(h.children(&entity(0)).is_empty());
+        assert_eq!(h.parent(&entity(1)), None);
+        assert_eq!(h.parent(&entity(2)), None);
+    }
+}
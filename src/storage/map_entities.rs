@@ -0,0 +1,148 @@
+use std::collections::{HashMap, VecDeque};
+
+use super::components::ComponentStorage;
+use super::entity::Entity;
+
+/// Rewrites all the entity references contained in a value
+/// (e.g. when the entities are moved to another world and get new ids).
+/// Usually implemented through the derive macro, that maps the fields marked
+/// with `#[entities]`:
+/// ```
+/// use wunderkammer::prelude::*;
+///
+/// #[derive(MapEntities)]
+/// struct Follow {
+///     #[entities]
+///     leader: Entity,
+///     distance: u32,
+/// }
+///
+/// let a = Entity { id: 0, version: 0 };
+/// let b = Entity { id: 7, version: 0 };
+/// let mut follow = Follow { leader: a, distance: 2 };
+/// follow.map_entities(&mut |e| if e == a { b } else { e });
+/// assert_eq!(follow.leader, b);
+/// ```
+pub trait MapEntities {
+    fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity);
+}
+
+impl MapEntities for Entity {
+    fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity) {
+        *self = mapper(*self);
+    }
+}
+impl<T: MapEntities> MapEntities for Option<T> {
+    fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity) {
+        if let Some(value) = self {
+            value.map_entities(mapper);
+        }
+    }
+}
+impl<T: MapEntities> MapEntities for Box<T> {
+    fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity) {
+        self.as_mut().map_entities(mapper);
+    }
+}
+impl<T: MapEntities, const N: usize> MapEntities for [T; N] {
+    fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity) {
+        self.iter_mut().for_each(|v| v.map_entities(mapper));
+    }
+}
+impl<T: MapEntities> MapEntities for Vec<T> {
+    fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity) {
+        self.iter_mut().for_each(|v| v.map_entities(mapper));
+    }
+}
+impl<T: MapEntities> MapEntities for VecDeque<T> {
+    fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity) {
+        self.iter_mut().for_each(|v| v.map_entities(mapper));
+    }
+}
+/// Only the values are mapped.
+impl<K, V: MapEntities> MapEntities for HashMap<K, V> {
+    fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity) {
+        self.values_mut().for_each(|v| v.map_entities(mapper));
+    }
+}
+/// Maps the component values (not the entities owning them).
+/// All the components are marked as changed.
+impl<T: MapEntities> MapEntities for ComponentStorage<T> {
+    fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity) {
+        self.values_mut().for_each(|v| v.map_entities(mapper));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use crate::storage::entity::IdSize;
+
+    fn entity(id: IdSize) -> Entity {
+        Entity { id, version: 0 }
+    }
+
+    fn shift(e: Entity) -> Entity {
+        Entity {
+            id: e.id + 10,
+            version: e.version,
+        }
+    }
+
+    #[test]
+    fn map_containers() {
+        let mut a = Some(vec![entity(0), entity(1)]);
+        a.map_entities(&mut shift);
+        assert_eq!(a, Some(vec![entity(10), entity(11)]));
+
+        let mut b = [entity(2); 2];
+        b.map_entities(&mut shift);
+        assert_eq!(b, [entity(12); 2]);
+    }
+
+    #[test]
+    fn derive_enum() {
+        #[derive(Debug, MapEntities, PartialEq)]
+        enum Goal {
+            Idle,
+            Attack(#[entities] Entity, u32),
+            Guard {
+                #[entities]
+                area: Vec<Entity>,
+                alert: bool,
+            },
+        }
+        let mut goals = vec![
+            Goal::Idle,
+            Goal::Attack(entity(1), 1),
+            Goal::Guard {
+                area: vec![entity(2)],
+                alert: true,
+            },
+        ];
+        goals.iter_mut().for_each(|g| g.map_entities(&mut shift));
+        assert_eq!(
+            goals,
+            vec![
+                Goal::Idle,
+                Goal::Attack(entity(11), 1),
+                Goal::Guard {
+                    area: vec![entity(12)],
+                    alert: true
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn derive_tuple_struct() {
+        #[derive(MapEntities)]
+        struct Pair(#[entities] Entity, #[entities] Option<Entity>, u8);
+
+        let mut pair = Pair(entity(0), None, 5);
+        pair.map_entities(&mut shift);
+        assert_eq!(pair.0, entity(10));
+        assert_eq!(pair.1, None);
+        assert_eq!(pair.2, 5);
+    }
+}
@@ -1,9 +1,44 @@
+pub(crate) mod bundle;
+pub(crate) mod checksum;
+pub(crate) mod commands;
 pub(crate) mod components;
+#[cfg(feature = "serialize")]
+pub(crate) mod console;
+pub(crate) mod diff;
 pub(crate) mod entity;
+#[cfg(feature = "serialize")]
+pub(crate) mod entity_data;
+pub(crate) mod entry;
+pub(crate) mod error;
+pub(crate) mod hierarchy;
+pub(crate) mod map_entities;
+#[cfg(feature = "serialize")]
+pub(crate) mod prefab;
 pub(crate) mod query;
+pub(crate) mod relation;
+pub(crate) mod schema;
 pub(crate) mod utils;
 pub(crate) mod world;
 
-pub use components::{ComponentSet, ComponentStorage};
+pub use bundle::Bundle;
+pub use checksum::StableHasher;
+pub use commands::Commands;
+#[cfg(feature = "serialize")]
+pub use components::JsonValue;
+pub use components::{ComponentSet, ComponentStorage, ForkComponents, Tick};
+pub use diff::{StorageDiff, StoragePatch, WorldPatch};
 pub use entity::{Entity, EntityStorage};
+#[cfg(feature = "serialize")]
+pub use entity_data::EntityData;
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use error::{ComponentError, WorldError};
+#[cfg(feature = "serialize")]
+pub use error::{ConsoleError, EntityDataError, PrefabError};
+pub use map_entities::MapEntities;
+#[cfg(feature = "serialize")]
+pub use prefab::{Prefab, PrefabLibrary};
+pub use relation::{Arity, CascadeDespawn, OnDespawn, Relation, RelationKind};
+#[cfg(feature = "serialize")]
+pub use schema::SerdeShape;
+pub use schema::{ComponentSchema, Schema};
 pub use world::WorldStorage;
@@ -0,0 +1,275 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::components::{ComponentSet, JsonValue};
+use super::entity::Entity;
+use super::error::PrefabError;
+use super::world::WorldStorage;
+
+/// Named entity template: serialized component values keyed by the
+/// `ComponentSet` field names, optionally inheriting from a parent prefab.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Prefab {
+    /// Components of the parent are spawned as well,
+    /// unless overridden by this prefab.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    #[serde(default)]
+    pub components: BTreeMap<String, JsonValue>,
+}
+
+/// Collection of the prefabs, keyed by their names.
+/// It can be loaded from JSON with `from_json` or from other serde
+/// formats (e.g. RON) with `from_deserializer`:
+/// ```
+/// use wunderkammer::prelude::*;
+///
+/// #[derive(ComponentSet, Default)]
+/// struct Components {
+///     #[component(serialize)]
+///     pub health: ComponentStorage<u32>,
+///     #[component(serialize)]
+///     pub name: ComponentStorage<String>,
+///     #[component(serialize)]
+///     pub monster: ComponentStorage<()>,
+/// }
+///
+/// let prefabs = PrefabLibrary::from_json(r#"{
+///     "monster": { "components": { "health": 5, "monster": null } },
+///     "rat": { "parent": "monster", "components": { "name": "Rat" } }
+/// }"#).unwrap();
+/// prefabs.validate::<Components>().unwrap();
+///
+/// let mut world = WorldStorage::<Components, ()>::default();
+/// let rat = world.spawn_prefab(&prefabs, "rat").unwrap();
+/// assert_eq!(world.cmps.health.get(&rat), Some(&5));
+/// assert_eq!(world.cmps.name.get(&rat), Some(&"Rat".to_string()));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PrefabLibrary {
+    prefabs: BTreeMap<String, Prefab>,
+}
+impl PrefabLibrary {
+    pub fn from_json(data: &str) -> Result<Self, PrefabError> {
+        serde_json::from_str(data).map_err(|e| PrefabError::Parse(e.to_string()))
+    }
+    /// Load the library from any serde format,
+    /// e.g. `from_deserializer(&mut ron::Deserializer::from_str(data)?)`.
+    pub fn from_deserializer<'de, D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, PrefabError> {
+        Self::deserialize(deserializer).map_err(|e| PrefabError::Parse(e.to_string()))
+    }
+    pub fn get(&self, name: &str) -> Option<&Prefab> {
+        self.prefabs.get(name)
+    }
+    /// Add (or replace) a prefab.
+    pub fn insert(&mut self, name: impl Into<String>, prefab: Prefab) -> Option<Prefab> {
+        self.prefabs.insert(name.into(), prefab)
+    }
+    /// Move all the prefabs from the other library (e.g. loaded from another
+    /// file) into this one. Prefabs of the same name are replaced.
+    pub fn append(&mut self, other: &mut PrefabLibrary) {
+        self.prefabs.append(&mut other.prefabs);
+    }
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.prefabs.keys().map(|k| k.as_str())
+    }
+    /// Check that all the prefabs can be spawned into worlds of the given
+    /// component set: their parents exist and the component values
+    /// are valid.
+    pub fn validate<C: ComponentSet + Default>(&self) -> Result<(), PrefabError> {
+        let mut cmps = C::default();
+        for (name, prefab) in self.prefabs.iter() {
+            self.resolve(name)?;
+            for (component, value) in prefab.components.iter() {
+                insert_component(&mut cmps, Entity::default(), name, component, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Components of the prefab, including the inherited ones.
+    /// Returned with the names of the prefabs that define the values.
+    fn resolve(&self, name: &str) -> Result<BTreeMap<&str, (&str, &JsonValue)>, PrefabError> {
+        let mut chain = Vec::new();
+        let mut current = Some(name);
+        while let Some(prefab_name) = current {
+            if chain.iter().any(|(n, _)| *n == prefab_name) {
+                return Err(PrefabError::InheritanceCycle(name.to_string()));
+            }
+            let (prefab_name, prefab) = self
+                .prefabs
+                .get_key_value(prefab_name)
+                .ok_or_else(|| PrefabError::UnknownPrefab(prefab_name.to_string()))?;
+            chain.push((prefab_name.as_str(), prefab));
+            current = prefab.parent.as_deref();
+        }
+
+        let mut components = BTreeMap::new();
+        // children override their parents
+        for (prefab_name, prefab) in chain.into_iter().rev() {
+            for (component, value) in prefab.components.iter() {
+                components.insert(component.as_str(), (prefab_name, value));
+            }
+        }
+        Ok(components)
+    }
+}
+
+impl<C: ComponentSet, R: Default> WorldStorage<C, R> {
+    /// Spawn a new entity with the prefab's components.
+    /// If any of the components can't be inserted, the entity is despawned
+    /// and the error is returned.
+    pub fn spawn_prefab(
+        &mut self,
+        prefabs: &PrefabLibrary,
+        name: &str,
+    ) -> Result<Entity, PrefabError> {
+        let components = prefabs.resolve(name)?;
+        let entity = self.try_spawn()?;
+        for (component, (prefab, value)) in components {
+            if let Err(e) = insert_component(&mut self.cmps, entity, prefab, component, value) {
+                self.despawn(entity);
+                return Err(e);
+            }
+        }
+        Ok(entity)
+    }
+}
+
+fn insert_component<C: ComponentSet>(
+    cmps: &mut C,
+    entity: Entity,
+    prefab: &str,
+    component: &str,
+    value: &JsonValue,
+) -> Result<(), PrefabError> {
+    cmps.insert_str_serialized(component, entity, value)
+        .map_err(|error| PrefabError::InvalidComponent {
+            prefab: prefab.to_string(),
+            component: component.to_string(),
+            value: value.to_string(),
+            error,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[derive(ComponentSet, Default)]
+    struct C {
+        #[component(serialize)]
+        pub health: ComponentStorage<u32>,
+        #[component(serialize)]
+        pub name: ComponentStorage<String>,
+        #[component(serialize)]
+        pub monster: ComponentStorage<()>,
+    }
+
+    const PREFABS: &str = r#"{
+        "monster": { "components": { "health": 5, "monster": null } },
+        "rat": { "parent": "monster", "components": { "name": "Rat" } },
+        "giant_rat": { "parent": "rat", "components": { "health": 20 } }
+    }"#;
+
+    #[test]
+    fn spawn_inherited() {
+        let prefabs = PrefabLibrary::from_json(PREFABS).unwrap();
+        assert_eq!(prefabs.validate::<C>(), Ok(()));
+        assert_eq!(
+            prefabs.names().collect::<Vec<_>>(),
+            vec!["giant_rat", "monster", "rat"]
+        );
+
+        let mut w = WorldStorage::<C, ()>::default();
+        let rat = w.spawn_prefab(&prefabs, "rat").unwrap();
+        let giant = w.spawn_prefab(&prefabs, "giant_rat").unwrap();
+        assert_eq!(w.cmps.health.get(&rat), Some(&5));
+        assert_eq!(w.cmps.health.get(&giant), Some(&20));
+        assert_eq!(w.cmps.name.get(&giant), Some(&"Rat".to_string()));
+        assert!(w.cmps.monster.get(&giant).is_some());
+        assert_eq!(
+            w.spawn_prefab(&prefabs, "dragon"),
+            Err(PrefabError::UnknownPrefab("dragon".to_string()))
+        );
+    }
+
+    #[test]
+    fn from_deserializer() {
+        let value: JsonValue = serde_json::from_str(PREFABS).unwrap();
+        let prefabs = PrefabLibrary::from_deserializer(value).unwrap();
+        assert_eq!(prefabs, PrefabLibrary::from_json(PREFABS).unwrap());
+        assert!(matches!(
+            PrefabLibrary::from_deserializer(serde_json::json!([1])),
+            Err(PrefabError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_value() {
+        let mut prefabs = PrefabLibrary::from_json(PREFABS).unwrap();
+        let mut broken = PrefabLibrary::from_json(
+            r#"{ "zombie_rat": { "parent": "rat", "components": { "health": -1 } } }"#,
+        )
+        .unwrap();
+        prefabs.append(&mut broken);
+
+        let Err(PrefabError::InvalidComponent {
+            prefab,
+            component,
+            value,
+            error,
+        }) = prefabs.validate::<C>()
+        else {
+            panic!("Invalid value not reported!")
+        };
+        assert_eq!(prefab, "zombie_rat");
+        assert_eq!(component, "health");
+        assert_eq!(value, "-1");
+        assert!(matches!(error, ComponentError::InvalidValue { .. }));
+
+        // the partially spawned entity is removed
+        let mut w = WorldStorage::<C, ()>::default();
+        assert!(w.spawn_prefab(&prefabs, "zombie_rat").is_err());
+        assert_eq!(w.entities().count(), 0);
+    }
+
+    #[test]
+    fn invalid_prefabs() {
+        let mut prefabs = PrefabLibrary::default();
+        let mut prefab = Prefab::default();
+        prefab
+            .components
+            .insert("mana".to_string(), serde_json::json!(3));
+        prefabs.insert("mage", prefab);
+        assert_eq!(
+            prefabs.validate::<C>().unwrap_err().to_string(),
+            "Prefab `mage` has invalid component `mana` value `3`: Unknown component `mana`"
+        );
+
+        let prefabs = PrefabLibrary::from_json(
+            r#"{
+                "a": { "parent": "b" },
+                "b": { "parent": "a" },
+                "c": { "parent": "d" }
+            }"#,
+        )
+        .unwrap();
+        let mut w = WorldStorage::<C, ()>::default();
+        assert_eq!(
+            w.spawn_prefab(&prefabs, "a"),
+            Err(PrefabError::InheritanceCycle("a".to_string()))
+        );
+        assert_eq!(
+            w.spawn_prefab(&prefabs, "c"),
+            Err(PrefabError::UnknownPrefab("d".to_string()))
+        );
+        assert!(matches!(
+            PrefabLibrary::from_json("{ \"a\": 1 }"),
+            Err(PrefabError::Parse(_))
+        ));
+    }
+}
@@ -1,26 +1,67 @@
 /// Base query that extracts matching entities from the World struct.
+/// The smallest of the `With` storages drives the iteration, so the order
+/// of the components does not affect the performance.
+///
+/// The `With` clause can be followed by any number of filters:
+/// - `Without(a, b)` - skip entities having any of the components
+/// - `Added(a, b; since)` - components inserted at or after the `since` tick
+/// - `Changed(a, b; since)` - components inserted or mutably accessed at or
+///   after the `since` tick
 #[macro_export]
 macro_rules! query {
-    ($world:expr, With($($components:ident), +), Without($($without:ident),+)) => {
+    (@filter $world:expr, $e:expr, Without($($without:ident),+)) => {
+        true $(&& $world.cmps.$without.get($e).is_none())+
+    };
+    (@filter $world:expr, $e:expr, Added($($added:ident),+; $since:expr)) => {
+        true $(&& $world.cmps.$added.is_added($e, $since))+
+    };
+    (@filter $world:expr, $e:expr, Changed($($changed:ident),+; $since:expr)) => {
+        true $(&& $world.cmps.$changed.is_changed($e, $since))+
+    };
+    ($world:expr, With($($components:ident),+) $(, $filter:ident($($args:tt)+))+) => {
         query!($world, With($($components),+))
-            $(.filter(|&e| $world.cmps.$without.get(e).is_none()))+
+            $(.filter(|&e| query!(@filter $world, e, $filter($($args)+))))+
     };
+    // pick the smallest storage to drive the iteration
+    (@driver $world:expr, $component:ident $(, $components:ident)*) => {{
+        let mut driver = $world.cmps.$component.entities();
+        $(
+            if $world.cmps.$components.len() < driver.len() {
+                driver = $world.cmps.$components.entities();
+            }
+        )*
+        driver
+    }};
     ($world:expr, With($component:ident)) => {
         $world.cmps.$component.entities()
     };
-    ($world:expr, With($component:ident, $($components:ident),+)) => {{
-        query!($world, With($($components),+))
-            .filter(|&e| $world.cmps.$component.get(e).is_some())
-    }};
+    ($world:expr, With($component:ident, $($components:ident),+)) => {
+        query!(@driver $world, $component, $($components),+)
+            .filter(|&e| {
+                $world.cmps.$component.get(e).is_some()
+                $(&& $world.cmps.$components.get(e).is_some())+
+            })
+    };
 }
 
 /// Query returning an immutable iterator over matching entities with their
 /// components.
+/// Accepts the same filters as `query!`.
+/// A `Maybe(a, b)` clause, placed directly after `With`, additionally yields
+/// `Option<&T>` for components that might be missing.
 #[macro_export]
 macro_rules! query_iter {
-    ($world:expr, With($($components:ident), +), Without($($without:ident),+)) => {
+    ($world:expr, With($($components:ident),+), Maybe($($maybe:ident),+) $(, $filter:ident($($args:tt)+))*) => {
+        query!($world, With($($components),+) $(, $filter($($args)+))*)
+            .map(|&e| (
+                e,
+                $( $world.cmps.$components.get(&e).unwrap(), )+
+                $( $world.cmps.$maybe.get(&e), )+
+            ))
+    };
+    ($world:expr, With($($components:ident),+) $(, $filter:ident($($args:tt)+))+) => {
         query_iter!($world, With($($components),+))
-            $(.filter(|a| $world.cmps.$without.get(&a.0).is_none()))+
+            $(.filter(|a| query!(@filter $world, &a.0, $filter($($args)+))))+
     };
     ($world:expr, With($component:ident)) => {
         $world
@@ -29,40 +70,108 @@ macro_rules! query_iter {
             .entities()
             .map(|&e| (e, $world.cmps.$component.get(&e).unwrap()))
     };
-    ($world:expr, With($component:ident, $($components:ident),+)) => {{
-        query_iter!($world, With($component))
-            .filter_map(|(e, c)| Some(
+    ($world:expr, With($component:ident, $($components:ident),+)) => {
+        query!(@driver $world, $component, $($components),+)
+            .filter_map(|&e| Some(
                 (
                     e,
-                    c,
+                    $world.cmps.$component.get(&e)?,
                     $( $world.cmps.$components.get(&e)?, )+
                 )
             ))
-    }};
+    };
 }
 
 /// Helper query that allows to execute a mutating closure on each matching
 /// entity and it's components.
+/// Accepts the same filters as `query!`, placed before the closure.
+/// Components listed in a `Maybe(a, b)` clause are passed as
+/// `Option<&mut T>`, after the required ones.
+///
+/// Does not allocate: the smallest storage is walked by it's dense index
+/// and the other components are fetched through sparse lookups.
 #[macro_export]
 macro_rules! query_execute {
-    ($world:expr, With($($components:ident), +), Without($($without:ident),+), $f:expr) => {
-        query!($world, With($($components),+), Without($($without),+))
-        // after querying should be always safe to unwrap
-            .copied()
-            .collect::<Vec<_>>()
-            .iter()
-            .for_each(|e| $f( e, $($world.cmps.$components.get_mut(&e).unwrap()),+ ))
+    // collect the optional components and filters one by one,
+    // until only the closure is left
+    (@munch $world:expr, $components:tt, ($($maybe:ident),*), $filters:tt, Maybe($($more:ident),+), $($rest:tt)+) => {
+        query_execute!(@munch $world, $components, ($($maybe,)* $($more),+), $filters, $($rest)+)
     };
-    ($world:expr, With($($components:ident), +),  $f:expr) => {
-        query!($world, With($($components),+))
-        // after querying should be always safe to unwrap
-            .copied()
-            .collect::<Vec<_>>()
-            .iter()
-            .for_each(|e| $f( e, $($world.cmps.$components.get_mut(&e).unwrap()),+ ))
+    (@munch $world:expr, $components:tt, $maybe:tt, ($($filters:tt)*), $filter:ident $args:tt, $($rest:tt)+) => {
+        query_execute!(@munch $world, $components, $maybe, ($($filters)* [$filter $args]), $($rest)+)
+    };
+    (@munch $world:expr, ($($components:ident),+), ($($maybe:ident),*), ($([$filter:ident $args:tt])*), $f:expr) => {{
+        let lens = [$( $world.cmps.$components.len() ),+];
+        let (driver, len) = lens.iter().copied().enumerate().min_by_key(|a| a.1).unwrap();
+        for i in 0..len {
+            // the closure can't modify the storage structure,
+            // so the dense indices remain stable
+            let e = [$( $world.cmps.$components.entities().as_slice() ),+][driver][i];
+            if !(true
+                $(&& $world.cmps.$components.get(&e).is_some())+
+                $(&& query!(@filter $world, &e, $filter $args))*)
+            {
+                continue;
+            }
+            // all the components are present, so it's safe to unwrap
+            $f(
+                &e,
+                $( $world.cmps.$components.get_mut(&e).unwrap(), )+
+                $( $world.cmps.$maybe.get_mut(&e), )*
+            );
+        }
+    }};
+    ($world:expr, With($($components:ident),+), $($rest:tt)+) => {
+        query_execute!(@munch $world, ($($components),+), (), (), $($rest)+)
     };
 }
 
+/// Helper that executes a mutating closure on a pair of distinct entities
+/// (e.g. an attacker and a defender).
+/// The closure receives `[Entity; 2]` followed by the components, passed as
+/// `[&mut T; 2]` arrays in the order of the entities.
+/// Evaluates to `Some` closure result, or `None` if any of the entities lacks
+/// a component or both entities are the same.
+/// ```
+/// use wunderkammer::prelude::*;
+///
+/// #[derive(ComponentSet, Default)]
+/// struct C {
+///     pub health: ComponentStorage<u32>,
+///     pub strength: ComponentStorage<u32>,
+/// }
+/// let mut world = WorldStorage::<C, ()>::default();
+/// let player = world.spawn();
+/// let rat = world.spawn();
+/// for e in [player, rat] {
+///     insert!(world, health, e, 5);
+///     insert!(world, strength, e, 2);
+/// }
+///
+/// query_execute_pair!(
+///     world,
+///     (player, rat),
+///     With(health, strength),
+///     |_, [_, h]: [&mut u32; 2], [s, _]: [&mut u32; 2]| *h -= *s
+/// );
+/// assert_eq!(world.cmps.health.get(&rat), Some(&3));
+/// ```
+#[macro_export]
+macro_rules! query_execute_pair {
+    ($world:expr, ($a:expr, $b:expr), With($($components:ident),+), $f:expr) => {{
+        let (a, b) = ($a, $b);
+        if a != b
+            $(&& $world.cmps.$components.get(&a).is_some()
+                && $world.cmps.$components.get(&b).is_some())+
+        {
+            // all the components are present, so it's safe to unwrap
+            Some($f([a, b], $( $world.cmps.$components.get_many_mut([&a, &b]).unwrap() ),+))
+        } else {
+            None
+        }
+    }};
+}
+
 #[cfg(test)]
 mod tests {
     use crate::prelude::*;
@@ -406,4 +515,277 @@ mod tests {
         assert_eq!(*w.cmps.health.get(&a).unwrap(), 16);
         assert_eq!(*w.cmps.health.get(&b).unwrap(), 17);
     }
+
+    #[test]
+    fn query_added() {
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+            pub name: ComponentStorage<String>,
+        }
+        #[derive(Default)]
+        struct R;
+        let mut w = WorldStorage::<C, R>::default();
+        let a = w.spawn();
+        let b = w.spawn();
+
+        insert!(w, health, a, 15);
+        insert!(w, name, a, "Fifteen".to_string());
+
+        w.increment_tick();
+        let since = w.tick();
+        insert!(w, health, b, 16);
+        insert!(w, name, b, "Sixteen".to_string());
+
+        let entities = query!(w, With(health), Added(health; since))
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(entities, vec![b]);
+
+        let v = query_iter!(w, With(health, name), Added(name; 0))
+            .map(|(_, h, _)| *h)
+            .collect::<Vec<_>>();
+        assert_eq!(v.len(), 2);
+    }
+
+    #[test]
+    fn query_changed() {
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+            pub name: ComponentStorage<String>,
+        }
+        #[derive(Default)]
+        struct R;
+        let mut w = WorldStorage::<C, R>::default();
+        let a = w.spawn();
+        let b = w.spawn();
+        let c = w.spawn();
+
+        insert!(w, health, a, 15);
+        insert!(w, health, b, 16);
+        insert!(w, health, c, 17);
+        insert!(w, name, c, "Seventeen".to_string());
+
+        w.increment_tick();
+        let since = w.tick();
+        *w.cmps.health.get_mut(&b).unwrap() += 1;
+        *w.cmps.health.get_mut(&c).unwrap() += 1;
+
+        let entities = query!(w, With(health), Changed(health; since), Without(name))
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(entities, vec![b]);
+
+        let v = query_iter!(w, With(health), Changed(health; since))
+            .map(|(_, h)| *h)
+            .collect::<Vec<_>>();
+        assert_eq!(v.iter().sum::<u32>(), 35);
+
+        w.increment_tick();
+        let since = w.tick();
+        query_execute!(w, With(health), Changed(health; since - 1), Without(name), |_, h: &mut u32| {
+            *h += 1;
+        });
+        assert_eq!(w.cmps.health.get(&a), Some(&15));
+        assert_eq!(w.cmps.health.get(&b), Some(&18));
+        assert_eq!(w.cmps.health.get(&c), Some(&18));
+        assert!(w.cmps.health.is_changed(&b, since));
+    }
+
+    #[test]
+    fn query_iter_maybe() {
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+            pub name: ComponentStorage<String>,
+            pub player: ComponentStorage<()>,
+            pub strength: ComponentStorage<u32>,
+        }
+        #[derive(Default)]
+        struct R;
+        let mut w = WorldStorage::<C, R>::default();
+        let a = w.spawn();
+        let b = w.spawn();
+        let c = w.spawn();
+
+        insert!(w, health, a, 15);
+        insert!(w, health, b, 16);
+        insert!(w, health, c, 17);
+
+        insert!(w, strength, a, 1);
+        insert!(w, name, a, "Fifteen".to_string());
+        insert!(w, player, c, ());
+
+        let mut v = query_iter!(w, With(health), Maybe(strength, name))
+            .map(|(e, h, s, n)| (e, *h, s.copied(), n.cloned()))
+            .collect::<Vec<_>>();
+        v.sort_by_key(|a| a.0);
+        assert_eq!(
+            v,
+            vec![
+                (a, 15, Some(1), Some("Fifteen".to_string())),
+                (b, 16, None, None),
+                (c, 17, None, None),
+            ]
+        );
+
+        let v = query_iter!(w, With(health), Maybe(strength), Without(player))
+            .map(|(_, h, s)| h + s.unwrap_or(&0))
+            .collect::<Vec<_>>();
+        assert_eq!(v.iter().sum::<u32>(), 32);
+    }
+
+    #[test]
+    fn query_execute_maybe() {
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+            pub player: ComponentStorage<()>,
+            pub shield: ComponentStorage<u32>,
+        }
+        #[derive(Default)]
+        struct R;
+        let mut w = WorldStorage::<C, R>::default();
+        let a = w.spawn();
+        let b = w.spawn();
+        let c = w.spawn();
+
+        insert!(w, health, a, 15);
+        insert!(w, health, b, 16);
+        insert!(w, health, c, 17);
+
+        insert!(w, shield, a, 3);
+        insert!(w, player, c, ());
+
+        query_execute!(
+            w,
+            With(health),
+            Maybe(shield),
+            Without(player),
+            |_, h: &mut u32, s: Option<&mut u32>| {
+                match s {
+                    Some(s) if *s > 0 => *s -= 1,
+                    _ => *h -= 1,
+                }
+            }
+        );
+
+        assert_eq!(w.cmps.health.get(&a), Some(&15));
+        assert_eq!(w.cmps.shield.get(&a), Some(&2));
+        assert_eq!(w.cmps.health.get(&b), Some(&15));
+        assert_eq!(w.cmps.health.get(&c), Some(&17));
+    }
+
+    #[test]
+    fn query_smallest_driver() {
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub pos: ComponentStorage<u32>,
+            pub ship: ComponentStorage<()>,
+        }
+        #[derive(Default)]
+        struct R;
+        let mut w = WorldStorage::<C, R>::default();
+        let entities = (0..10).map(|_| w.spawn()).collect::<Vec<_>>();
+        for (i, e) in entities.iter().enumerate() {
+            insert!(w, pos, *e, i as u32);
+        }
+        insert!(w, ship, entities[7], ());
+        insert!(w, ship, entities[2], ());
+
+        // the iteration follows the (smaller) ship storage order
+        let expected = vec![entities[7], entities[2]];
+        assert_eq!(
+            query!(w, With(pos, ship)).copied().collect::<Vec<_>>(),
+            expected
+        );
+        assert_eq!(
+            query!(w, With(ship, pos)).copied().collect::<Vec<_>>(),
+            expected
+        );
+        assert_eq!(
+            query_iter!(w, With(pos, ship))
+                .map(|(_, p, _)| *p)
+                .collect::<Vec<_>>(),
+            vec![7, 2]
+        );
+    }
+
+    #[test]
+    fn query_execute_smallest_driver() {
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub pos: ComponentStorage<u32>,
+            pub vel: ComponentStorage<u32>,
+            pub ship: ComponentStorage<()>,
+        }
+        #[derive(Default)]
+        struct R;
+        let mut w = WorldStorage::<C, R>::default();
+        let entities = (0..10).map(|_| w.spawn()).collect::<Vec<_>>();
+        for e in entities.iter() {
+            insert!(w, pos, *e, 0);
+            insert!(w, vel, *e, 2);
+        }
+        insert!(w, ship, entities[7], ());
+        insert!(w, ship, entities[2], ());
+
+        fn apply_velocity(_: &Entity, p: &mut u32, v: &mut u32, _: &mut ()) {
+            *p += *v;
+        }
+        query_execute!(w, With(pos, vel, ship), apply_velocity);
+
+        let moved = query_iter!(w, With(pos))
+            .filter(|(_, p)| **p > 0)
+            .map(|(e, _)| e)
+            .collect::<Vec<_>>();
+        assert_eq!(moved, vec![entities[2], entities[7]]);
+    }
+
+    #[test]
+    fn query_execute_pair() {
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+            pub strength: ComponentStorage<u32>,
+        }
+        #[derive(Default)]
+        struct R;
+        let mut w = WorldStorage::<C, R>::default();
+        let a = w.spawn();
+        let b = w.spawn();
+        let c = w.spawn();
+
+        insert!(w, health, a, 10);
+        insert!(w, strength, a, 3);
+        insert!(w, health, b, 8);
+        insert!(w, strength, b, 2);
+        insert!(w, health, c, 5);
+
+        let attack = |_, [_, h]: [&mut u32; 2], [s, _]: [&mut u32; 2]| {
+            *h -= *s;
+            *h
+        };
+
+        assert_eq!(
+            query_execute_pair!(w, (a, b), With(health, strength), attack),
+            Some(5)
+        );
+        assert_eq!(
+            query_execute_pair!(w, (b, a), With(health, strength), attack),
+            Some(8)
+        );
+        // missing component
+        assert_eq!(
+            query_execute_pair!(w, (a, c), With(health, strength), attack),
+            None
+        );
+        assert_eq!(w.cmps.health.get(&c), Some(&5));
+        // the same entity
+        assert_eq!(
+            query_execute_pair!(w, (a, a), With(health, strength), attack),
+            None
+        );
+    }
 }
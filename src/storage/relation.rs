@@ -0,0 +1,430 @@
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+#[cfg(feature = "serialize")]
+use super::components::JsonValue;
+use super::components::{ComponentStorage, Tick};
+use super::entity::Entity;
+
+/// How many relations the entities can have.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Arity {
+    /// A source can have many targets, but a target only one source
+    /// (e.g. `Owns`).
+    OneToMany,
+    /// A source can have only one target (e.g. `Targets`).
+    ManyToOne,
+    ManyToMany,
+}
+
+/// What happens to the relations when their target is despawned.
+/// Relations of a despawned source are always removed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OnDespawn {
+    /// Remove the relations pointing to the target.
+    Remove,
+    /// Despawn the sources as well.
+    Cascade,
+    /// Keep the dangling target in the sources' relations.
+    Keep,
+}
+
+/// Describes a kind of relation (usually implemented by a marker struct).
+/// ```
+/// use wunderkammer::prelude::*;
+///
+/// // turrets are despawned together with their ship
+/// struct AttachedTo;
+/// impl RelationKind for AttachedTo {
+///     const ARITY: Arity = Arity::ManyToOne;
+///     const ON_DESPAWN: OnDespawn = OnDespawn::Cascade;
+/// }
+///
+/// #[derive(ComponentSet, Default)]
+/// struct C {
+///     pub attached_to: Relation<AttachedTo>,
+/// }
+/// let mut world = WorldStorage::<C, ()>::default();
+/// let ship = world.spawn();
+/// let turret = world.spawn();
+/// insert!(world, attached_to, turret, ship);
+///
+/// assert_eq!(world.cmps.attached_to.sources(&ship), &[turret]);
+/// world.despawn(ship);
+/// assert!(!world.is_valid(&turret));
+/// ```
+pub trait RelationKind {
+    const ARITY: Arity;
+    const ON_DESPAWN: OnDespawn;
+}
+
+/// Relation storage, usable as a `ComponentSet` field.
+/// Keeps both the forward (source -> targets)
+/// and the reverse (target -> sources) indices.
+/// It can be used in the queries, like a component storage holding
+/// the targets.
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialize", serde(bound = ""))]
+pub struct Relation<K> {
+    forward: ComponentStorage<Vec<Entity>>,
+    reverse: ComponentStorage<Vec<Entity>>,
+    kind: PhantomData<K>,
+}
+impl<K: RelationKind> Relation<K> {
+    /// Targets of the source.
+    pub fn get(&self, source: &Entity) -> Option<&[Entity]> {
+        self.forward.get(source).map(|a| a.as_slice())
+    }
+    pub fn targets(&self, source: &Entity) -> &[Entity] {
+        self.get(source).unwrap_or_default()
+    }
+    pub fn sources(&self, target: &Entity) -> &[Entity] {
+        self.reverse
+            .get(target)
+            .map(|a| a.as_slice())
+            .unwrap_or_default()
+    }
+    pub fn contains(&self, source: &Entity, target: &Entity) -> bool {
+        self.targets(source).contains(target)
+    }
+    /// Entities that are a source of any relation.
+    pub fn entities(&self) -> std::slice::Iter<'_, Entity> {
+        self.forward.entities()
+    }
+    /// Number of the source entities.
+    pub fn len(&self) -> usize {
+        self.forward.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.forward.is_empty()
+    }
+    pub fn is_added(&self, source: &Entity, since: Tick) -> bool {
+        self.forward.is_added(source, since)
+    }
+    pub fn is_changed(&self, source: &Entity, since: Tick) -> bool {
+        self.forward.is_changed(source, since)
+    }
+    // Relate the source with the target.
+    // Existing relations that would violate the arity are removed.
+    // Since it cannot validate the entities,
+    // it is recommended to use `insert!` macro that calls it internally
+    // (it validates only the source though).
+    pub fn __insert(&mut self, source: Entity, target: Entity) {
+        if self.contains(&source, &target) {
+            return;
+        }
+        match K::ARITY {
+            Arity::OneToMany => {
+                for s in self.sources(&target).to_vec() {
+                    self.unrelate(s, target);
+                }
+            }
+            Arity::ManyToOne => {
+                for t in self.targets(&source).to_vec() {
+                    self.unrelate(source, t);
+                }
+            }
+            Arity::ManyToMany => (),
+        }
+        self.forward.entry(source).or_default().push(target);
+        self.reverse.entry(target).or_default().push(source);
+    }
+    /// Remove a single relation. Returns false if it did not exist.
+    pub fn unrelate(&mut self, source: Entity, target: Entity) -> bool {
+        let removed = Self::remove_from(&mut self.forward, source, target);
+        if removed {
+            Self::remove_from(&mut self.reverse, target, source);
+        }
+        removed
+    }
+    /// Remove the entity from the relation (called on despawn).
+    /// Returns the entity's targets.
+    /// Relations targeting the entity are kept if the kind's despawn
+    /// policy is `OnDespawn::Keep`.
+    pub fn remove(&mut self, entity: Entity) -> Option<Vec<Entity>> {
+        if let Some(sources) = self.reverse.remove(entity) {
+            if K::ON_DESPAWN != OnDespawn::Keep {
+                for source in sources {
+                    Self::remove_from(&mut self.forward, source, entity);
+                }
+            }
+        }
+        self.remove_targets(entity)
+    }
+    /// Remove the source's relations, keeping the ones targeting it.
+    /// Returns the removed targets.
+    pub fn remove_targets(&mut self, source: Entity) -> Option<Vec<Entity>> {
+        let targets = self.forward.remove(source)?;
+        for &target in targets.iter() {
+            Self::remove_from(&mut self.reverse, target, source);
+        }
+        Some(targets)
+    }
+    /// Used by the `ComponentSet` derive.
+    /// Only the relations where the entity is the source are removed.
+    pub fn __remove_str(&mut self, entity: Entity) -> bool {
+        self.remove_targets(entity).is_some()
+    }
+    /// Move the entity's relations (where it is the source) from the other
+    /// storage into this one, mapping both the source and the targets.
+    /// Relations that can't be mapped are dropped, as are the `Arity::OneToMany`
+    /// ones whose target already has a source in this storage.
+    pub fn move_entity(
+        &mut self,
+        other: &mut Self,
+        entity: Entity,
+        mapper: &mut dyn FnMut(Entity) -> Option<Entity>,
+    ) {
+        for target in other.targets(&entity).to_vec() {
+            other.unrelate(entity, target);
+            if let (Some(source), Some(target)) = (mapper(entity), mapper(target)) {
+                if K::ARITY == Arity::OneToMany && !self.sources(&target).is_empty() {
+                    continue;
+                }
+                self.__insert(source, target);
+            }
+        }
+    }
+    /// Copy the source entity's relations to the destination one.
+    /// Does nothing for the `Arity::OneToMany` relations, as the targets
+    /// would have been taken away from the source.
+    pub fn clone_entity(&mut self, src: Entity, dst: Entity) {
+        if K::ARITY == Arity::OneToMany {
+            return;
+        }
+        for target in self.targets(&src).to_vec() {
+            self.__insert(dst, target);
+        }
+    }
+    /// Copy-on-write copy of the relation storage.
+    pub fn fork(&mut self) -> Self {
+        Self {
+            forward: self.forward.fork(),
+            reverse: self.reverse.fork(),
+            kind: PhantomData,
+        }
+    }
+    /// Normally called through `WorldStorage::increment_tick`.
+    pub fn set_tick(&mut self, tick: Tick) {
+        self.forward.set_tick(tick);
+        self.reverse.set_tick(tick);
+    }
+
+    fn remove_from(index: &mut ComponentStorage<Vec<Entity>>, key: Entity, value: Entity) -> bool {
+        let Some(values) = index.get_mut(&key) else {
+            return false;
+        };
+        let Some(i) = values.iter().position(|&v| v == value) else {
+            return false;
+        };
+        values.remove(i);
+        if values.is_empty() {
+            let _ = index.remove(key);
+        }
+        true
+    }
+}
+/// Target lists as JSON values, for the string keyed access.
+#[cfg(feature = "serialize")]
+impl<K: RelationKind> Relation<K> {
+    /// Get the source's targets serialized as a JSON array.
+    pub fn get_serialized(&self, source: &Entity) -> Result<Option<JsonValue>, serde_json::Error> {
+        self.get(source).map(serde_json::to_value).transpose()
+    }
+    /// Replace the source's targets with ones deserialized from a JSON array.
+    /// Returns the previous targets.
+    pub fn __insert_serialized(
+        &mut self,
+        source: Entity,
+        value: &JsonValue,
+    ) -> Result<Option<Vec<Entity>>, serde_json::Error> {
+        self.__insert_serialized_targets(source, value, &mut |e| e)
+    }
+    /// Same as `__insert_serialized`, but the targets are mapped.
+    pub fn __insert_serialized_targets(
+        &mut self,
+        source: Entity,
+        value: &JsonValue,
+        mapper: &mut dyn FnMut(Entity) -> Entity,
+    ) -> Result<Option<Vec<Entity>>, serde_json::Error> {
+        let targets = Vec::<Entity>::deserialize(value)?;
+        let previous = self.get(&source).map(|t| t.to_vec());
+        for &target in previous.iter().flatten() {
+            self.unrelate(source, target);
+        }
+        for target in targets {
+            self.__insert(source, mapper(target));
+        }
+        Ok(previous)
+    }
+}
+/// Hashes the sources and their targets (in the source id order).
+impl<K> Hash for Relation<K> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.forward.hash(state);
+    }
+}
+impl<K> Clone for Relation<K> {
+    fn clone(&self) -> Self {
+        Self {
+            forward: self.forward.clone(),
+            reverse: self.reverse.clone(),
+            kind: PhantomData,
+        }
+    }
+}
+impl<K> Default for Relation<K> {
+    fn default() -> Self {
+        Self {
+            forward: ComponentStorage::default(),
+            reverse: ComponentStorage::default(),
+            kind: PhantomData,
+        }
+    }
+}
+
+/// Allows the `ComponentSet` fields to request despawning of additional
+/// entities, together with the given one.
+pub trait CascadeDespawn {
+    fn cascade_despawn(&self, _entity: &Entity) -> Vec<Entity> {
+        Vec::new()
+    }
+}
+impl<T> CascadeDespawn for ComponentStorage<T> {}
+impl<K: RelationKind> CascadeDespawn for Relation<K> {
+    fn cascade_despawn(&self, entity: &Entity) -> Vec<Entity> {
+        match K::ON_DESPAWN {
+            OnDespawn::Cascade => self.sources(entity).to_vec(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::entity::IdSize;
+
+    fn entity(id: IdSize) -> Entity {
+        Entity { id, version: 0 }
+    }
+
+    struct Likes;
+    impl RelationKind for Likes {
+        const ARITY: Arity = Arity::ManyToMany;
+        const ON_DESPAWN: OnDespawn = OnDespawn::Remove;
+    }
+    struct Owns;
+    impl RelationKind for Owns {
+        const ARITY: Arity = Arity::OneToMany;
+        const ON_DESPAWN: OnDespawn = OnDespawn::Cascade;
+    }
+    struct Targets;
+    impl RelationKind for Targets {
+        const ARITY: Arity = Arity::ManyToOne;
+        const ON_DESPAWN: OnDespawn = OnDespawn::Keep;
+    }
+
+    #[test]
+    fn many_to_many() {
+        let mut r = Relation::<Likes>::default();
+        r.__insert(entity(0), entity(1));
+        r.__insert(entity(0), entity(2));
+        r.__insert(entity(3), entity(1));
+        r.__insert(entity(3), entity(1));
+
+        assert_eq!(r.targets(&entity(0)), &[entity(1), entity(2)]);
+        assert_eq!(r.sources(&entity(1)), &[entity(0), entity(3)]);
+        assert_eq!(r.len(), 2);
+
+        assert!(r.unrelate(entity(0), entity(1)));
+        assert!(!r.unrelate(entity(0), entity(1)));
+        assert_eq!(r.sources(&entity(1)), &[entity(3)]);
+    }
+
+    #[test]
+    fn one_to_many() {
+        let mut r = Relation::<Owns>::default();
+        r.__insert(entity(0), entity(2));
+        r.__insert(entity(0), entity(3));
+        // the item changes the owner
+        r.__insert(entity(1), entity(2));
+
+        assert_eq!(r.targets(&entity(0)), &[entity(3)]);
+        assert_eq!(r.targets(&entity(1)), &[entity(2)]);
+        assert_eq!(r.sources(&entity(2)), &[entity(1)]);
+    }
+
+    #[test]
+    fn many_to_one() {
+        let mut r = Relation::<Targets>::default();
+        r.__insert(entity(0), entity(2));
+        r.__insert(entity(1), entity(2));
+        // retarget
+        r.__insert(entity(0), entity(3));
+
+        assert_eq!(r.targets(&entity(0)), &[entity(3)]);
+        assert_eq!(r.sources(&entity(2)), &[entity(1)]);
+        assert_eq!(r.sources(&entity(3)), &[entity(0)]);
+    }
+
+    #[test]
+    fn remove() {
+        let mut r = Relation::<Likes>::default();
+        r.__insert(entity(0), entity(1));
+        r.__insert(entity(1), entity(2));
+        r.__insert(entity(2), entity(1));
+
+        assert_eq!(r.remove(entity(1)), Some(vec![entity(2)]));
+        assert!(r.targets(&entity(0)).is_empty());
+        assert!(r.targets(&entity(2)).is_empty());
+        assert!(r.sources(&entity(2)).is_empty());
+        assert!(r.is_empty());
+    }
+
+    #[test]
+    fn remove_targets() {
+        let mut r = Relation::<Likes>::default();
+        r.__insert(entity(0), entity(1));
+        r.__insert(entity(1), entity(2));
+
+        assert_eq!(r.remove_targets(entity(1)), Some(vec![entity(2)]));
+        assert_eq!(r.remove_targets(entity(1)), None);
+        assert_eq!(r.targets(&entity(0)), &[entity(1)]);
+        assert!(r.sources(&entity(2)).is_empty());
+    }
+
+    #[test]
+    fn remove_keep() {
+        let mut r = Relation::<Targets>::default();
+        r.__insert(entity(0), entity(1));
+        r.remove(entity(1));
+
+        assert_eq!(r.targets(&entity(0)), &[entity(1)]);
+        assert!(r.sources(&entity(1)).is_empty());
+        assert!(r.cascade_despawn(&entity(1)).is_empty());
+    }
+
+    #[test]
+    fn clone_entity() {
+        let mut likes = Relation::<Likes>::default();
+        likes.__insert(entity(0), entity(1));
+        likes.clone_entity(entity(0), entity(2));
+        assert_eq!(likes.sources(&entity(1)), &[entity(0), entity(2)]);
+
+        let mut owns = Relation::<Owns>::default();
+        owns.__insert(entity(0), entity(1));
+        owns.clone_entity(entity(0), entity(2));
+        assert!(owns.targets(&entity(2)).is_empty());
+    }
+
+    #[test]
+    fn cascade() {
+        let mut r = Relation::<Owns>::default();
+        r.__insert(entity(0), entity(1));
+        assert_eq!(r.cascade_despawn(&entity(1)), vec![entity(0)]);
+        assert!(r.cascade_despawn(&entity(0)).is_empty());
+    }
+}
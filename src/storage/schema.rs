@@ -0,0 +1,369 @@
+#[cfg(feature = "serialize")]
+use serde::Serialize;
+use std::any::type_name;
+
+use super::components::ComponentStorage;
+#[cfg(feature = "serialize")]
+use super::entity::Entity;
+use super::relation::{Relation, RelationKind};
+
+/// Description of the component set, generated by the `ComponentSet` derive
+/// (e.g. for the editor tooling).
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct Schema {
+    /// Components in the field order.
+    pub components: Vec<ComponentSchema>,
+}
+impl Schema {
+    pub fn get(&self, name: &str) -> Option<&ComponentSchema> {
+        self.components.iter().find(|c| c.name == name)
+    }
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.components.iter().map(|c| c.name)
+    }
+    /// Export the schema as a pretty printed JSON document.
+    #[cfg(feature = "serialize")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Schema is always serializable!")
+    }
+}
+
+/// Description of a single component storage.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct ComponentSchema {
+    /// Field name of the storage.
+    pub name: &'static str,
+    /// Rust type name of the component (or the relation storage).
+    /// Not guaranteed to be stable between the compiler versions.
+    pub type_name: &'static str,
+    /// The component is a `()` marker.
+    pub marker: bool,
+    /// Structure of the component, as seen by serde.
+    /// Set only for the `#[component(serialize)]` fields.
+    #[cfg(feature = "serialize")]
+    pub shape: Option<SerdeShape>,
+}
+
+impl<T> ComponentStorage<T> {
+    /// Used by the `ComponentSet` derive.
+    pub fn schema(name: &'static str) -> ComponentSchema {
+        ComponentSchema {
+            name,
+            type_name: type_name::<T>(),
+            marker: type_name::<T>() == "()",
+            #[cfg(feature = "serialize")]
+            shape: None,
+        }
+    }
+}
+#[cfg(feature = "serialize")]
+impl<T: serde::de::DeserializeOwned> ComponentStorage<T> {
+    /// Used by the `ComponentSet` derive.
+    pub fn serde_schema(name: &'static str) -> ComponentSchema {
+        ComponentSchema {
+            shape: Some(SerdeShape::of::<T>()),
+            ..Self::schema(name)
+        }
+    }
+}
+impl<K: RelationKind> Relation<K> {
+    /// Used by the `ComponentSet` derive.
+    pub fn schema(name: &'static str) -> ComponentSchema {
+        ComponentSchema {
+            name,
+            type_name: type_name::<Self>(),
+            marker: false,
+            #[cfg(feature = "serialize")]
+            shape: None,
+        }
+    }
+    /// Used by the `ComponentSet` derive.
+    /// Relations are serialized as the target lists.
+    #[cfg(feature = "serialize")]
+    pub fn serde_schema(name: &'static str) -> ComponentSchema {
+        ComponentSchema {
+            shape: Some(SerdeShape::of::<Vec<Entity>>()),
+            ..Self::schema(name)
+        }
+    }
+}
+
+/// Top level structure of a type in the serde data model.
+/// Nested types are not described.
+#[cfg(feature = "serialize")]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SerdeShape {
+    /// Named after the serde data model type (e.g. `u32` or `string`).
+    Primitive {
+        name: &'static str,
+    },
+    Option,
+    Unit,
+    Seq,
+    Map,
+    Tuple {
+        len: usize,
+    },
+    UnitStruct {
+        name: &'static str,
+    },
+    NewtypeStruct {
+        name: &'static str,
+    },
+    TupleStruct {
+        name: &'static str,
+        len: usize,
+    },
+    Struct {
+        name: &'static str,
+        fields: &'static [&'static str],
+    },
+    Enum {
+        name: &'static str,
+        variants: &'static [&'static str],
+    },
+    /// Self describing (e.g. untagged enums) or custom deserialized types.
+    Unknown,
+}
+#[cfg(feature = "serialize")]
+impl SerdeShape {
+    /// Trace the shape of the type through it's `Deserialize` implementation.
+    pub fn of<T: serde::de::DeserializeOwned>() -> Self {
+        match T::deserialize(ShapeTracer) {
+            Err(Traced(Some(shape))) => shape,
+            _ => SerdeShape::Unknown,
+        }
+    }
+}
+
+/// Deserializer that fails on the first request, reporting the requested shape.
+#[cfg(feature = "serialize")]
+struct ShapeTracer;
+
+#[cfg(feature = "serialize")]
+#[derive(Debug)]
+struct Traced(Option<SerdeShape>);
+#[cfg(feature = "serialize")]
+impl std::fmt::Display for Traced {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+#[cfg(feature = "serialize")]
+impl std::error::Error for Traced {}
+#[cfg(feature = "serialize")]
+impl serde::de::Error for Traced {
+    fn custom<M: std::fmt::Display>(_: M) -> Self {
+        Traced(None)
+    }
+}
+
+#[cfg(feature = "serialize")]
+macro_rules! trace_primitives {
+    ($($method:ident => $name:literal,)*) => {
+        $(
+            fn $method<V: serde::de::Visitor<'de>>(self, _: V) -> Result<V::Value, Traced> {
+                Err(Traced(Some(SerdeShape::Primitive { name: $name })))
+            }
+        )*
+    };
+}
+
+#[cfg(feature = "serialize")]
+impl<'de> serde::Deserializer<'de> for ShapeTracer {
+    type Error = Traced;
+
+    trace_primitives! {
+        deserialize_bool => "bool",
+        deserialize_i8 => "i8",
+        deserialize_i16 => "i16",
+        deserialize_i32 => "i32",
+        deserialize_i64 => "i64",
+        deserialize_i128 => "i128",
+        deserialize_u8 => "u8",
+        deserialize_u16 => "u16",
+        deserialize_u32 => "u32",
+        deserialize_u64 => "u64",
+        deserialize_u128 => "u128",
+        deserialize_f32 => "f32",
+        deserialize_f64 => "f64",
+        deserialize_char => "char",
+        deserialize_str => "string",
+        deserialize_string => "string",
+        deserialize_bytes => "bytes",
+        deserialize_byte_buf => "bytes",
+        deserialize_identifier => "identifier",
+    }
+
+    fn deserialize_any<V: serde::de::Visitor<'de>>(self, _: V) -> Result<V::Value, Traced> {
+        Err(Traced(Some(SerdeShape::Unknown)))
+    }
+    fn deserialize_ignored_any<V: serde::de::Visitor<'de>>(self, _: V) -> Result<V::Value, Traced> {
+        Err(Traced(Some(SerdeShape::Unknown)))
+    }
+    fn deserialize_option<V: serde::de::Visitor<'de>>(self, _: V) -> Result<V::Value, Traced> {
+        Err(Traced(Some(SerdeShape::Option)))
+    }
+    fn deserialize_unit<V: serde::de::Visitor<'de>>(self, _: V) -> Result<V::Value, Traced> {
+        Err(Traced(Some(SerdeShape::Unit)))
+    }
+    fn deserialize_seq<V: serde::de::Visitor<'de>>(self, _: V) -> Result<V::Value, Traced> {
+        Err(Traced(Some(SerdeShape::Seq)))
+    }
+    fn deserialize_map<V: serde::de::Visitor<'de>>(self, _: V) -> Result<V::Value, Traced> {
+        Err(Traced(Some(SerdeShape::Map)))
+    }
+    fn deserialize_tuple<V: serde::de::Visitor<'de>>(
+        self,
+        len: usize,
+        _: V,
+    ) -> Result<V::Value, Traced> {
+        Err(Traced(Some(SerdeShape::Tuple { len })))
+    }
+    fn deserialize_unit_struct<V: serde::de::Visitor<'de>>(
+        self,
+        name: &'static str,
+        _: V,
+    ) -> Result<V::Value, Traced> {
+        Err(Traced(Some(SerdeShape::UnitStruct { name })))
+    }
+    fn deserialize_newtype_struct<V: serde::de::Visitor<'de>>(
+        self,
+        name: &'static str,
+        _: V,
+    ) -> Result<V::Value, Traced> {
+        Err(Traced(Some(SerdeShape::NewtypeStruct { name })))
+    }
+    fn deserialize_tuple_struct<V: serde::de::Visitor<'de>>(
+        self,
+        name: &'static str,
+        len: usize,
+        _: V,
+    ) -> Result<V::Value, Traced> {
+        Err(Traced(Some(SerdeShape::TupleStruct { name, len })))
+    }
+    fn deserialize_struct<V: serde::de::Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        _: V,
+    ) -> Result<V::Value, Traced> {
+        Err(Traced(Some(SerdeShape::Struct { name, fields })))
+    }
+    fn deserialize_enum<V: serde::de::Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        _: V,
+    ) -> Result<V::Value, Traced> {
+        Err(Traced(Some(SerdeShape::Enum { name, variants })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    struct Likes;
+    impl RelationKind for Likes {
+        const ARITY: Arity = Arity::ManyToMany;
+        const ON_DESPAWN: OnDespawn = OnDespawn::Remove;
+    }
+
+    #[test]
+    fn derive_schema() {
+        #[derive(Clone, Debug, Hash, PartialEq)]
+        struct Stats;
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+            pub stats: ComponentStorage<Stats>,
+            pub player: ComponentStorage<()>,
+            pub likes: Relation<Likes>,
+        }
+        let schema = C::schema();
+        assert_eq!(
+            schema.names().collect::<Vec<_>>(),
+            vec!["health", "stats", "player", "likes"]
+        );
+        assert_eq!(schema.get("health").unwrap().type_name, "u32");
+        assert!(schema.get("stats").unwrap().type_name.ends_with("::Stats"));
+        assert!(schema.get("likes").unwrap().type_name.contains("Relation<"));
+        assert!(schema.get("player").unwrap().marker);
+        assert!(!schema.get("health").unwrap().marker);
+        assert!(schema.get("mana").is_none());
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn serde_shape() {
+        #[derive(serde::Deserialize)]
+        #[allow(dead_code)]
+        struct Stats {
+            hp: u32,
+            name: String,
+        }
+        #[derive(serde::Deserialize)]
+        #[allow(dead_code)]
+        enum Mood {
+            Calm,
+            Angry(u8),
+        }
+        assert_eq!(
+            SerdeShape::of::<Stats>(),
+            SerdeShape::Struct {
+                name: "Stats",
+                fields: &["hp", "name"]
+            }
+        );
+        assert_eq!(
+            SerdeShape::of::<Mood>(),
+            SerdeShape::Enum {
+                name: "Mood",
+                variants: &["Calm", "Angry"]
+            }
+        );
+        assert_eq!(
+            SerdeShape::of::<f32>(),
+            SerdeShape::Primitive { name: "f32" }
+        );
+        assert_eq!(SerdeShape::of::<Vec<u8>>(), SerdeShape::Seq);
+        assert_eq!(SerdeShape::of::<JsonValue>(), SerdeShape::Unknown);
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn schema_json() {
+        struct Handle;
+        #[derive(ComponentSet, Default)]
+        struct C {
+            #[component(serialize)]
+            pub health: ComponentStorage<u32>,
+            #[component(serialize)]
+            pub player: ComponentStorage<()>,
+            #[component(serialize)]
+            pub likes: Relation<Likes>,
+            #[component(no_clone, no_hash, no_debug)]
+            pub handle: ComponentStorage<Handle>,
+        }
+        let schema = C::schema();
+        assert_eq!(schema.get("likes").unwrap().shape, Some(SerdeShape::Seq));
+        assert_eq!(schema.get("handle").unwrap().shape, None);
+
+        let json: JsonValue = serde_json::from_str(&schema.to_json()).unwrap();
+        assert_eq!(
+            json["components"][0],
+            serde_json::json!({
+                "name": "health",
+                "type_name": "u32",
+                "marker": false,
+                "shape": { "kind": "primitive", "name": "u32" }
+            })
+        );
+        assert_eq!(json["components"][1]["shape"]["kind"], "unit");
+        assert_eq!(json["components"][1]["marker"], true);
+    }
+}
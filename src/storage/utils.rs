@@ -7,6 +7,22 @@ macro_rules! insert {
     };
 }
 
+/// Checked version of `insert!`.
+/// Returns the previous component value, or an error if the entity
+/// is not valid.
+/// The entity expression is evaluated once.
+#[macro_export]
+macro_rules! try_insert {
+    ($world:expr, $component:ident, $entity:expr, $value:expr) => {
+        match $entity {
+            entity => match $world.validate(&entity) {
+                Ok(()) => Ok($world.cmps.$component.__insert(entity, $value)),
+                Err(e) => Err(e),
+            },
+        }
+    };
+}
+
 #[cfg(test)]
 mod tests {
     use crate::prelude::*;
@@ -41,4 +57,35 @@ mod tests {
         insert!(w, health, entity, 15);
         assert_eq!(w.cmps.health.get(&entity), None);
     }
+
+    #[test]
+    fn test_try_insert() {
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+        }
+        #[derive(Default)]
+        struct R;
+        let mut w = WorldStorage::<C, R>::default();
+        let entity = w.spawn();
+
+        assert_eq!(try_insert!(w, health, entity, 15), Ok(None));
+        assert_eq!(try_insert!(w, health, entity, 16), Ok(Some(15)));
+
+        w.despawn(entity);
+        assert_eq!(
+            try_insert!(w, health, entity, 17),
+            Err(WorldError::DeadEntity(entity))
+        );
+        assert_eq!(w.cmps.health.get(&entity), None);
+
+        let spawned = [w.spawn(), w.spawn()];
+        let mut entities = spawned.iter().copied();
+        assert_eq!(
+            try_insert!(w, health, entities.next().unwrap(), 18),
+            Ok(None)
+        );
+        assert_eq!(w.cmps.health.get(&spawned[0]), Some(&18));
+        assert_eq!(entities.next(), Some(spawned[1]));
+    }
 }
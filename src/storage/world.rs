@@ -1,50 +1,1245 @@
 #[cfg(feature = "serialize")]
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
 
-use super::components::ComponentSet;
+use super::bundle::Bundle;
+use super::checksum::StableHasher;
+use super::commands::Commands;
+use super::components::{ComponentSet, ComponentStorage, ForkComponents, Tick};
+use super::diff::WorldPatch;
 use super::entity::{Entity, EntityStorage};
+use super::entry::Entry;
+use super::error::WorldError;
+use super::hierarchy::Hierarchy;
 
 /// Main storage struct responsible for tracking entities, components and
 /// resources.
-#[derive(Default)]
+#[derive(Clone, Default)]
 #[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub struct WorldStorage<C, R> {
-    entities: EntityStorage,
+    pub(crate) entities: EntityStorage,
+    // missing in the worlds saved by the older versions
+    #[cfg_attr(feature = "serialize", serde(default))]
+    tick: Tick,
+    #[cfg_attr(feature = "serialize", serde(default))]
+    hierarchy: Hierarchy,
     pub cmps: C,
     pub res: R,
 }
 impl<C: ComponentSet, R: Default> WorldStorage<C, R> {
+    /// Spawn a new entity.
+    /// Panics if the entity id space is exhausted.
     pub fn spawn(&mut self) -> Entity {
         self.entities.spawn()
     }
+    /// Spawn a new entity, or return an error if the id space is exhausted.
+    pub fn try_spawn(&mut self) -> Result<Entity, WorldError> {
+        self.entities.try_spawn()
+    }
+    /// Cheap copy of the world (e.g. for AI simulations).
+    /// The component storages are copied on write, so the fork only pays
+    /// for the storages that are actually modified (by either of the worlds).
+    /// Component sets with `#[component(no_clone)]` fields can't be forked:
+    /// ```compile_fail
+    /// use wunderkammer::prelude::*;
+    ///
+    /// struct Handle;
+    /// #[derive(ComponentSet, Default)]
+    /// struct Components {
+    ///     #[component(no_clone, no_hash, no_debug)]
+    ///     pub handle: ComponentStorage<Handle>,
+    /// }
+    ///
+    /// let mut world = WorldStorage::<Components, ()>::default();
+    /// let fork = world.fork();
+    /// ```
+    pub fn fork(&mut self) -> Self
+    where
+        C: ForkComponents,
+        R: Clone,
+    {
+        Self {
+            entities: self.entities.clone(),
+            tick: self.tick,
+            hierarchy: self.hierarchy.clone(),
+            cmps: self.cmps.fork(),
+            res: self.res.clone(),
+        }
+    }
+    /// Deterministic hash of the world state (e.g. for desync detection).
+    /// Includes the live entities with their parents and the components
+    /// (except for the `#[component(no_hash)]` fields), hashed in the entity
+    /// id order.
+    /// Resources and ticks are not included.
+    pub fn checksum(&self) -> u64 {
+        let mut hasher = StableHasher::default();
+        for entity in self.entities() {
+            entity.hash(&mut hasher);
+            self.parent(entity).hash(&mut hasher);
+        }
+        self.cmps.hash_components(&mut hasher);
+        hasher.finish()
+    }
+    /// Changes that turn this world into the other one (e.g. a fork or
+    /// a snapshot). Resources and the `#[component(no_clone)]` /
+    /// `#[component(no_diff)]` fields are not included.
+    pub fn diff(&self, other: &Self) -> WorldPatch<C::Patch> {
+        let mut spawned = other
+            .entities()
+            .filter(|e| !self.is_valid(e))
+            .copied()
+            .collect::<Vec<_>>();
+        let mut despawned = self
+            .entities()
+            .filter(|e| !other.is_valid(e))
+            .copied()
+            .collect::<Vec<_>>();
+        spawned.sort();
+        despawned.sort();
+        let parents = other
+            .entities()
+            .map(|e| (*e, other.parent(e)))
+            .filter(|(e, parent)| self.parent(e) != *parent)
+            .collect();
+        WorldPatch {
+            spawned,
+            despawned,
+            parents,
+            components: self.cmps.diff(&other.cmps),
+            recycled: other.entities.recycled().into(),
+            slots: other.entities.slots(),
+        }
+    }
+    /// Replay the changes created by `diff`.
+    /// The world should be in the same state as the one the patch was
+    /// created from.
+    /// The despawned entities lose all their components (including
+    /// the ones excluded from the diffs).
+    pub fn apply_patch(&mut self, patch: &WorldPatch<C::Patch>) {
+        for entity in patch.despawned.iter() {
+            self.hierarchy.remove(*entity);
+            self.cmps.remove_all_components(*entity);
+        }
+        self.entities.apply_changes(
+            &patch.spawned,
+            &patch.despawned,
+            &patch.recycled,
+            patch.slots,
+        );
+        // detach first, so the intermediate hierarchy can't have cycles
+        for (child, _) in patch.parents.iter() {
+            self.hierarchy.remove_parent(*child);
+        }
+        for (child, parent) in patch.parents.iter() {
+            if let Some(parent) = parent {
+                self.hierarchy.set_parent(*child, *parent);
+            }
+        }
+        self.cmps.apply_patch(&patch.components);
+    }
+    /// Spawn a copy of the entity, with all it's components
+    /// (except for the `#[component(no_clone)]` fields) and the same parent.
+    /// Panics if the entity is not valid or the id space is exhausted.
+    pub fn clone_entity(&mut self, entity: Entity) -> Entity {
+        self.try_clone_entity(entity)
+            .expect("Can't clone the entity!")
+    }
+    /// Spawn a copy of the entity, or return an error if it is not valid
+    /// or the id space is exhausted.
+    pub fn try_clone_entity(&mut self, entity: Entity) -> Result<Entity, WorldError> {
+        self.validate(&entity)?;
+        let clone = self.try_spawn()?;
+        self.cmps.clone_entity(entity, clone);
+        if let Some(parent) = self.hierarchy.parent(&entity) {
+            self.hierarchy.set_parent(clone, parent);
+        }
+        Ok(clone)
+    }
+    /// Get the entity's component entry from the storage selected by the
+    /// accessor (e.g. `|c| &mut c.health`), for in-place manipulation.
+    /// Returns `None` if the entity is not valid.
+    pub fn entry<T>(
+        &mut self,
+        entity: Entity,
+        storage: impl FnOnce(&mut C) -> &mut ComponentStorage<T>,
+    ) -> Option<Entry<'_, T>> {
+        if !self.is_valid(&entity) {
+            return None;
+        }
+        Some(storage(&mut self.cmps).entry(entity))
+    }
+    /// Spawn a new entity with all the bundle's components.
+    pub fn spawn_bundle<B: Bundle<C>>(&mut self, bundle: B) -> Entity {
+        let entity = self.spawn();
+        bundle.insert_into(&mut self.cmps, entity);
+        entity
+    }
+    /// Insert all the bundle's components (overwriting the existing ones).
+    /// Like `insert!`, does nothing if the entity is not valid.
+    pub fn insert_bundle<B: Bundle<C>>(&mut self, entity: Entity, bundle: B) {
+        if self.is_valid(&entity) {
+            bundle.insert_into(&mut self.cmps, entity);
+        }
+    }
+    /// Remove all the bundle's components and return them.
+    /// Nothing is removed if the entity lacks any of the components.
+    pub fn remove_bundle<B: Bundle<C>>(&mut self, entity: Entity) -> Option<B> {
+        B::remove_from(&mut self.cmps, entity)
+    }
+    /// Despawn the entity. It's children (if any) become roots.
+    /// Invalid entities are ignored.
     pub fn despawn(&mut self, entity: Entity) {
-        self.cmps.remove_all_components(entity);
-        self.entities.despawn(entity);
+        let _ = self.try_despawn(entity);
+    }
+    /// Despawn the entity, or return an error if it is not valid.
+    /// Sources of the entity's cascading relations are despawned as well.
+    pub fn try_despawn(&mut self, entity: Entity) -> Result<(), WorldError> {
+        self.entities.validate(&entity)?;
+        // worklist instead of recursion, as the cascades can be long chains
+        let mut pending = vec![entity];
+        while let Some(entity) = pending.pop() {
+            if !self.is_valid(&entity) {
+                continue;
+            }
+            pending.extend(self.cmps.cascade_despawn(&entity).into_iter().rev());
+            self.hierarchy.remove(entity);
+            self.cmps.remove_all_components(entity);
+            self.entities.despawn(entity);
+        }
+        Ok(())
+    }
+    /// Despawn the entity together with all it's descendants.
+    pub fn despawn_recursive(&mut self, entity: Entity) {
+        if !self.is_valid(&entity) {
+            return;
+        }
+        for descendant in self.hierarchy.descendants(&entity) {
+            self.despawn(descendant);
+        }
+        self.despawn(entity);
     }
     pub fn is_valid(&self, entity: &Entity) -> bool {
         self.entities.is_valid(entity)
     }
+    /// Validates the entity, reporting the reason of a failure.
+    pub fn validate(&self, entity: &Entity) -> Result<(), WorldError> {
+        self.entities.validate(entity)
+    }
+    /// Get the entity's component from the storage selected by the accessor
+    /// (e.g. `|c| &c.health`), or an error if the entity is not valid
+    /// or lacks the component.
+    pub fn try_get<T>(
+        &self,
+        entity: &Entity,
+        storage: impl FnOnce(&C) -> &ComponentStorage<T>,
+    ) -> Result<&T, WorldError> {
+        self.validate(entity)?;
+        storage(&self.cmps)
+            .get(entity)
+            .ok_or(WorldError::MissingComponent(*entity))
+    }
     pub fn entities(&self) -> impl Iterator<Item = &Entity> + use<'_, C, R> {
         self.entities.all()
     }
+    /// Move all the entities of the other world (e.g. a deserialized level)
+    /// into this one. See `merge_from` for the details.
+    /// The other world's resources are dropped.
+    pub fn import(&mut self, mut other: WorldStorage<C, R>) -> HashMap<Entity, Entity> {
+        self.merge_from(&mut other)
+    }
+    /// Move all the entities of the other world into this one, leaving it
+    /// empty (but keeping it's resources). The entities get fresh ids and the
+    /// references to them are remapped in the components, relations and
+    /// hierarchy. Only the `#[component(map_entities)]` component values are
+    /// remapped. References to the entities missing from the other world are
+    /// dropped (as in `extract`). Returns the old -> new entity mapping.
+    pub fn merge_from(&mut self, other: &mut WorldStorage<C, R>) -> HashMap<Entity, Entity> {
+        let entities = other.entities().copied().collect::<Vec<_>>();
+        self.move_entities(other, &entities)
+    }
+    /// Move the entities into a new world (e.g. to unload a level).
+    /// Relations to the entities left behind are dropped, as are the
+    /// `#[component(map_entities)]` values referencing them.
+    /// Invalid entities are skipped.
+    /// Returns the new world and the old -> new entity mapping.
+    pub fn extract(&mut self, entities: &[Entity]) -> (WorldStorage<C, R>, HashMap<Entity, Entity>)
+    where
+        C: Default,
+    {
+        let mut world = WorldStorage::default();
+        let mapping = world.move_entities(self, entities);
+        (world, mapping)
+    }
+    fn move_entities(
+        &mut self,
+        other: &mut WorldStorage<C, R>,
+        entities: &[Entity],
+    ) -> HashMap<Entity, Entity> {
+        let mut moved = Vec::new();
+        let mut mapping = HashMap::new();
+        for &entity in entities {
+            if other.is_valid(&entity) && !mapping.contains_key(&entity) {
+                mapping.insert(entity, self.spawn());
+                moved.push(entity);
+            }
+        }
+        let mut mapper = |entity: Entity| mapping.get(&entity).copied();
+
+        for &entity in moved.iter() {
+            self.cmps.move_entity(&mut other.cmps, entity, &mut mapper);
+        }
+        for &entity in moved.iter() {
+            if let Some(parent) = other.hierarchy.parent(&entity) {
+                if mapping.contains_key(&parent) {
+                    self.hierarchy
+                        .set_parent(mapping[&entity], mapping[&parent]);
+                }
+            }
+        }
+        // the entities are moved, so the cascading relations are not triggered
+        for entity in moved {
+            other.hierarchy.remove(entity);
+            other.cmps.remove_all_components(entity);
+            other.entities.despawn(entity);
+        }
+        mapping
+    }
+    /// Attach the child to the parent entity (detaching it from the previous
+    /// one). Returns false if any of the entities is not valid or the relation
+    /// would create a cycle.
+    pub fn set_parent(&mut self, child: Entity, parent: Entity) -> bool {
+        if !self.is_valid(&child) || !self.is_valid(&parent) {
+            return false;
+        }
+        self.hierarchy.set_parent(child, parent)
+    }
+    /// Detach the entity from it's parent. Returns the previous parent.
+    pub fn remove_parent(&mut self, child: Entity) -> Option<Entity> {
+        if !self.is_valid(&child) {
+            return None;
+        }
+        self.hierarchy.remove_parent(child)
+    }
+    pub fn parent(&self, entity: &Entity) -> Option<Entity> {
+        if !self.is_valid(entity) {
+            return None;
+        }
+        self.hierarchy.parent(entity)
+    }
+    pub fn children(&self, entity: &Entity) -> &[Entity] {
+        if !self.is_valid(entity) {
+            return &[];
+        }
+        self.hierarchy.children(entity)
+    }
+    /// Iterate from the direct parent of the entity up to the root.
+    pub fn ancestors(&self, entity: &Entity) -> impl Iterator<Item = Entity> + use<'_, C, R> {
+        std::iter::successors(self.parent(entity), |e| self.hierarchy.parent(e))
+    }
+    /// Current world tick. Component insertions and mutable accesses are
+    /// marked with it.
+    pub fn tick(&self) -> Tick {
+        self.tick
+    }
+    /// Advance the world tick (e.g. once per frame or turn).
+    /// The tick wraps around on overflow.
+    pub fn increment_tick(&mut self) {
+        self.tick = self.tick.wrapping_add(1);
+        self.cmps.set_tick(self.tick);
+    }
+    /// Create a deferred command buffer.
+    /// Entities spawned through it are reserved in the world, so they are
+    /// not handed out by the world's own spawns or other buffers.
+    pub fn commands(&self) -> Commands<C, R>
+    where
+        C: 'static,
+        R: 'static,
+    {
+        Commands::new(self.entities.reservations())
+    }
+}
+
+/// Dumps all the live entities with their components.
+impl<C: ComponentSet, R: Debug> Debug for WorldStorage<C, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WorldStorage")
+            .field("tick", &self.tick)
+            .field("entities", &EntitiesDebug(self))
+            .field("res", &self.res)
+            .finish()
+    }
+}
+
+struct EntitiesDebug<'a, C, R>(&'a WorldStorage<C, R>);
+impl<C: ComponentSet, R> Debug for EntitiesDebug<'_, C, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let world = self.0;
+        f.debug_map()
+            .entries(world.entities.all().map(|e| {
+                (
+                    RawDebug(e.to_string()),
+                    RawDebug(world.cmps.debug_entity(*e)),
+                )
+            }))
+            .finish()
+    }
+}
+
+// Already formatted value, written without the quotes.
+struct RawDebug(String);
+impl Debug for RawDebug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     #[allow(unused_imports)]
     use crate::prelude::*;
+    use crate::storage::entity::IdSize;
+
+    #[test]
+    fn increment_tick() {
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+            pub name: ComponentStorage<String>,
+        }
+        #[derive(Default)]
+        struct R;
+        let mut w = WorldStorage::<C, R>::default();
+        let a = w.spawn();
+        insert!(w, health, a, 15);
+
+        w.increment_tick();
+        assert_eq!(w.tick(), 1);
+        insert!(w, name, a, "Fifteen".to_string());
+
+        assert!(!w.cmps.health.is_added(&a, w.tick()));
+        assert!(w.cmps.name.is_added(&a, w.tick()));
+
+        w.despawn(a);
+        assert_eq!(w.cmps.health.drain_removed().collect::<Vec<_>>(), vec![a]);
+    }
 
     #[cfg(feature = "serialize")]
     use serde::{Deserialize, Serialize};
 
+    #[test]
+    fn try_despawn() {
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+        }
+        let mut w = WorldStorage::<C, ()>::default();
+        let a = w.spawn();
+        insert!(w, health, a, 5);
+
+        assert_eq!(w.try_despawn(a), Ok(()));
+        assert_eq!(w.cmps.health.get(&a), None);
+        assert_eq!(w.try_despawn(a), Err(WorldError::DeadEntity(a)));
+
+        let unknown = Entity { id: 7, version: 0 };
+        w.despawn(unknown);
+        assert_eq!(w.try_despawn(unknown), Err(WorldError::UnknownId(unknown)));
+    }
+
+    #[test]
+    fn try_get() {
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+            pub name: ComponentStorage<String>,
+        }
+        let mut w = WorldStorage::<C, ()>::default();
+        let a = w.spawn();
+        insert!(w, health, a, 5);
+
+        assert_eq!(w.try_get(&a, |c| &c.health), Ok(&5));
+        assert_eq!(
+            w.try_get(&a, |c| &c.name),
+            Err(WorldError::MissingComponent(a))
+        );
+
+        w.despawn(a);
+        let b = w.spawn();
+        assert_eq!(
+            w.try_get(&a, |c| &c.health),
+            Err(WorldError::StaleVersion(a))
+        );
+        assert_eq!(
+            w.try_get(&b, |c| &c.health),
+            Err(WorldError::MissingComponent(b))
+        );
+    }
+
+    #[test]
+    fn entry() {
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub kills: ComponentStorage<u32>,
+        }
+        let mut w = WorldStorage::<C, ()>::default();
+        let a = w.spawn();
+
+        for _ in 0..3 {
+            w.entry(a, |c| &mut c.kills)
+                .unwrap()
+                .and_modify(|k| *k += 1)
+                .or_insert(1);
+        }
+        assert_eq!(w.cmps.kills.get(&a), Some(&3));
+
+        w.despawn(a);
+        assert!(w.entry(a, |c| &mut c.kills).is_none());
+        assert_eq!(w.cmps.kills.get(&a), None);
+    }
+
+    #[test]
+    fn bundle() {
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+            pub name: ComponentStorage<String>,
+            pub poison: ComponentStorage<()>,
+        }
+        #[derive(Bundle, Debug, PartialEq)]
+        #[bundle(C)]
+        struct Unit {
+            health: u32,
+            name: String,
+        }
+        let mut w = WorldStorage::<C, ()>::default();
+        let a = w.spawn_bundle(Unit {
+            health: 15,
+            name: "Fifteen".to_string(),
+        });
+        let b = w.spawn();
+        w.insert_bundle(
+            b,
+            Unit {
+                health: 16,
+                name: "Sixteen".to_string(),
+            },
+        );
+        insert!(w, poison, b, ());
+
+        assert_eq!(query!(w, With(health, name)).count(), 2);
+        assert_eq!(w.cmps.name.get(&a), Some(&"Fifteen".to_string()));
+
+        assert_eq!(
+            w.remove_bundle::<Unit>(b),
+            Some(Unit {
+                health: 16,
+                name: "Sixteen".to_string()
+            })
+        );
+        assert!(w.cmps.poison.get(&b).is_some());
+
+        // incomplete bundles are not removed
+        let _ = w.cmps.name.remove(a);
+        assert_eq!(w.remove_bundle::<Unit>(a), None);
+        assert_eq!(w.cmps.health.get(&a), Some(&15));
+
+        // dead entities are ignored
+        w.despawn(b);
+        w.insert_bundle(
+            b,
+            Unit {
+                health: 17,
+                name: "Seventeen".to_string(),
+            },
+        );
+        assert_eq!(w.cmps.health.get(&b), None);
+    }
+
+    #[test]
+    fn hierarchy() {
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub name: ComponentStorage<String>,
+        }
+        let mut w = WorldStorage::<C, ()>::default();
+        let inventory = w.spawn();
+        let chest = w.spawn();
+        let sword = w.spawn();
+
+        assert!(w.set_parent(chest, inventory));
+        assert!(w.set_parent(sword, chest));
+        assert!(!w.set_parent(inventory, sword));
+
+        assert_eq!(w.children(&inventory), &[chest]);
+        assert_eq!(
+            w.ancestors(&sword).collect::<Vec<_>>(),
+            vec![chest, inventory]
+        );
+
+        // move the sword directly into the inventory
+        assert!(w.set_parent(sword, inventory));
+        assert!(w.children(&chest).is_empty());
+        assert_eq!(w.children(&inventory), &[chest, sword]);
+
+        assert_eq!(w.remove_parent(sword), Some(inventory));
+        assert_eq!(w.parent(&sword), None);
+    }
+
+    #[test]
+    fn despawn_recursive() {
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub name: ComponentStorage<String>,
+        }
+        let mut w = WorldStorage::<C, ()>::default();
+        let inventory = w.spawn();
+        let chest = w.spawn();
+        let sword = w.spawn();
+        let shield = w.spawn();
+        insert!(w, name, sword, "Sword".to_string());
+
+        w.set_parent(chest, inventory);
+        w.set_parent(sword, chest);
+        w.set_parent(shield, inventory);
+
+        w.despawn_recursive(chest);
+        assert!(!w.is_valid(&chest));
+        assert!(!w.is_valid(&sword));
+        assert!(w.is_valid(&shield));
+        assert_eq!(w.cmps.name.get(&sword), None);
+        assert_eq!(w.children(&inventory), &[shield]);
+
+        // recycled entity does not inherit the relations
+        let recycled = w.spawn();
+        assert_eq!(recycled.id, sword.id);
+        assert_eq!(w.parent(&recycled), None);
+    }
+
+    #[test]
+    fn despawn_orphans_children() {
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub name: ComponentStorage<String>,
+        }
+        let mut w = WorldStorage::<C, ()>::default();
+        let chest = w.spawn();
+        let sword = w.spawn();
+        w.set_parent(sword, chest);
+
+        w.despawn(chest);
+        assert!(w.is_valid(&sword));
+        assert_eq!(w.parent(&sword), None);
+    }
+
+    #[test]
+    fn relations() {
+        struct Owns;
+        impl RelationKind for Owns {
+            const ARITY: Arity = Arity::OneToMany;
+            const ON_DESPAWN: OnDespawn = OnDespawn::Cascade;
+        }
+        struct Targets;
+        impl RelationKind for Targets {
+            const ARITY: Arity = Arity::ManyToOne;
+            const ON_DESPAWN: OnDespawn = OnDespawn::Remove;
+        }
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+            pub owns: Relation<Owns>,
+            pub targets: Relation<Targets>,
+        }
+        let mut w = WorldStorage::<C, ()>::default();
+        let a = w.spawn();
+        let b = w.spawn();
+        let c = w.spawn();
+        insert!(w, health, a, 5);
+        insert!(w, health, b, 5);
+        insert!(w, targets, a, c);
+        insert!(w, targets, b, c);
+        // cyclic ownership should not loop
+        insert!(w, owns, a, b);
+        insert!(w, owns, b, a);
+
+        assert_eq!(w.cmps.targets.sources(&c), &[a, b]);
+        assert_eq!(query!(w, With(health, targets)).count(), 2);
+
+        w.despawn(c);
+        assert!(w.cmps.targets.is_empty());
+        assert!(w.is_valid(&a));
+
+        w.despawn(a);
+        assert!(!w.is_valid(&b));
+        assert!(w.cmps.owns.is_empty());
+    }
+
+    #[test]
+    fn cascade_long_chain() {
+        struct Contains;
+        impl RelationKind for Contains {
+            const ARITY: Arity = Arity::OneToMany;
+            const ON_DESPAWN: OnDespawn = OnDespawn::Cascade;
+        }
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub contains: Relation<Contains>,
+        }
+        let len = 100_000.min(IdSize::MAX as usize - 1);
+        let mut w = WorldStorage::<C, ()>::default();
+        let first = w.spawn();
+        let mut inner = first;
+        for _ in 1..len {
+            let outer = w.spawn();
+            insert!(w, contains, outer, inner);
+            inner = outer;
+        }
+        w.despawn(first);
+        assert_eq!(w.entities().count(), 0);
+        assert!(w.cmps.contains.is_empty());
+    }
+
+    #[test]
+    fn import() {
+        struct Likes;
+        impl RelationKind for Likes {
+            const ARITY: Arity = Arity::ManyToMany;
+            const ON_DESPAWN: OnDespawn = OnDespawn::Remove;
+        }
+        #[derive(Clone, Debug, Hash, MapEntities, PartialEq)]
+        struct Follow {
+            #[entities]
+            leader: Entity,
+        }
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub name: ComponentStorage<String>,
+            #[component(map_entities)]
+            pub follow: ComponentStorage<Follow>,
+            pub likes: Relation<Likes>,
+        }
+        let mut level = WorldStorage::<C, ()>::default();
+        let dead = level.spawn();
+        let a = level.spawn();
+        let b = level.spawn();
+        level.despawn(dead);
+        insert!(level, name, a, "A".to_string());
+        insert!(level, follow, b, Follow { leader: a });
+        insert!(level, likes, a, b);
+        level.set_parent(b, a);
+
+        let mut w = WorldStorage::<C, ()>::default();
+        let c = w.spawn();
+        insert!(w, name, c, "C".to_string());
+
+        let mapping = w.import(level);
+        assert_eq!(mapping.len(), 2);
+        let (a, b) = (mapping[&a], mapping[&b]);
+        assert!(a != c && b != c);
+
+        assert_eq!(w.cmps.name.get(&a), Some(&"A".to_string()));
+        assert_eq!(w.cmps.name.get(&c), Some(&"C".to_string()));
+        assert_eq!(w.cmps.follow.get(&b).unwrap().leader, a);
+        assert_eq!(w.cmps.likes.targets(&a), &[b]);
+        assert_eq!(w.parent(&b), Some(a));
+    }
+
+    #[test]
+    fn merge_from() {
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+        }
+        #[derive(Default)]
+        struct R {
+            depth: u32,
+        }
+        let mut w = WorldStorage::<C, R>::default();
+        let a = w.spawn();
+        insert!(w, health, a, 1);
+
+        let mut floor = WorldStorage::<C, R>::default();
+        floor.res.depth = 2;
+        let b = floor.spawn();
+        let c = floor.spawn();
+        insert!(floor, health, b, 2);
+        floor.set_parent(c, b);
+
+        let mapping = w.merge_from(&mut floor);
+        assert_eq!(floor.entities().count(), 0);
+        assert!(floor.cmps.health.is_empty());
+        assert_eq!(floor.res.depth, 2);
+
+        assert_eq!(w.cmps.health.get(&a), Some(&1));
+        assert_eq!(w.cmps.health.get(&mapping[&b]), Some(&2));
+        assert_eq!(w.children(&mapping[&b]), &[mapping[&c]]);
+    }
+
+    #[test]
+    fn extract() {
+        struct Owns;
+        impl RelationKind for Owns {
+            const ARITY: Arity = Arity::OneToMany;
+            const ON_DESPAWN: OnDespawn = OnDespawn::Cascade;
+        }
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+            pub owns: Relation<Owns>,
+        }
+        let mut w = WorldStorage::<C, ()>::default();
+        let player = w.spawn();
+        let rat = w.spawn();
+        let cheese = w.spawn();
+        let sword = w.spawn();
+        insert!(w, health, player, 10);
+        insert!(w, health, rat, 2);
+        insert!(w, owns, rat, cheese);
+        insert!(w, owns, player, sword);
+        w.set_parent(rat, player);
+
+        let (floor, mapping) = w.extract(&[rat, cheese, sword, rat]);
+        assert_eq!(mapping.len(), 3);
+        assert_eq!(floor.entities().count(), 3);
+        assert_eq!(floor.cmps.health.get(&mapping[&rat]), Some(&2));
+        assert_eq!(floor.cmps.owns.targets(&mapping[&rat]), &[mapping[&cheese]]);
+        assert_eq!(floor.parent(&mapping[&rat]), None);
+
+        // the owner is not despawned, as the sword has only been moved
+        assert!(w.is_valid(&player));
+        assert!(!w.is_valid(&rat));
+        assert!(w.cmps.owns.is_empty());
+        assert!(w.children(&player).is_empty());
+        assert_eq!(w.cmps.health.len(), 1);
+    }
+
+    #[test]
+    fn extract_boundary() {
+        struct Likes;
+        impl RelationKind for Likes {
+            const ARITY: Arity = Arity::ManyToMany;
+            const ON_DESPAWN: OnDespawn = OnDespawn::Remove;
+        }
+        #[derive(Clone, Debug, Hash, MapEntities, PartialEq)]
+        struct Follow {
+            #[entities]
+            leader: Entity,
+        }
+        #[derive(ComponentSet, Default)]
+        struct C {
+            #[component(map_entities)]
+            pub follow: ComponentStorage<Follow>,
+            pub likes: Relation<Likes>,
+        }
+        let mut w = WorldStorage::<C, ()>::default();
+        let player = w.spawn();
+        let rat = w.spawn();
+        let bat = w.spawn();
+        insert!(w, likes, rat, player);
+        insert!(w, likes, rat, bat);
+        insert!(w, follow, rat, Follow { leader: player });
+        insert!(w, follow, bat, Follow { leader: rat });
+
+        let (floor, mapping) = w.extract(&[rat, bat]);
+        let (rat, bat) = (mapping[&rat], mapping[&bat]);
+        // the player is left behind, so it's not referenced anymore
+        assert_eq!(floor.cmps.likes.targets(&rat), &[bat]);
+        assert!(floor.cmps.follow.get(&rat).is_none());
+        assert_eq!(floor.cmps.follow.get(&bat).unwrap().leader, rat);
+
+        assert!(w.cmps.likes.is_empty());
+        assert!(w.cmps.follow.is_empty());
+    }
+
+    #[test]
+    fn clone_entity() {
+        struct Likes;
+        impl RelationKind for Likes {
+            const ARITY: Arity = Arity::ManyToMany;
+            const ON_DESPAWN: OnDespawn = OnDespawn::Remove;
+        }
+        struct Handle;
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+            pub name: ComponentStorage<String>,
+            #[component(no_clone, no_hash, no_debug)]
+            pub handle: ComponentStorage<Handle>,
+            pub likes: Relation<Likes>,
+        }
+        let mut w = WorldStorage::<C, ()>::default();
+        let chest = w.spawn();
+        let potion = w.spawn();
+        insert!(w, health, potion, 5);
+        insert!(w, name, potion, "Potion".to_string());
+        insert!(w, handle, potion, Handle);
+        insert!(w, likes, potion, chest);
+        w.set_parent(potion, chest);
+
+        let copy = w.clone_entity(potion);
+        assert_ne!(copy, potion);
+        assert_eq!(w.cmps.health.get(&copy), Some(&5));
+        assert_eq!(w.cmps.name.get(&copy), Some(&"Potion".to_string()));
+        assert!(w.cmps.handle.get(&copy).is_none());
+        assert_eq!(w.cmps.likes.sources(&chest), &[potion, copy]);
+        assert_eq!(w.children(&chest), &[potion, copy]);
+
+        w.despawn(potion);
+        assert_eq!(
+            w.try_clone_entity(potion),
+            Err(WorldError::DeadEntity(potion))
+        );
+    }
+
+    #[test]
+    fn fork() {
+        struct Likes;
+        impl RelationKind for Likes {
+            const ARITY: Arity = Arity::ManyToMany;
+            const ON_DESPAWN: OnDespawn = OnDespawn::Remove;
+        }
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+            pub name: ComponentStorage<String>,
+            pub likes: Relation<Likes>,
+        }
+        #[derive(Clone, Default)]
+        struct R {
+            turn: u32,
+        }
+        let mut w = WorldStorage::<C, R>::default();
+        let a = w.spawn();
+        insert!(w, health, a, 5);
+        insert!(w, name, a, "A".to_string());
+        insert!(w, likes, a, a);
+
+        // every storage is shared with the fork
+        let mut sim = w.fork();
+        assert_eq!(sim.cmps.health.get(&a), Some(&5));
+        assert_eq!(sim.cmps.name.get(&a), Some(&"A".to_string()));
+        assert_eq!(sim.cmps.likes.targets(&a), &[a]);
+        sim.res.turn += 1;
+        *sim.cmps.health.get_mut(&a).unwrap() -= 1;
+        let b = sim.spawn();
+        insert!(sim, health, b, 1);
+        sim.despawn(a);
+
+        assert!(w.is_valid(&a));
+        assert!(!w.is_valid(&b));
+        assert_eq!(w.res.turn, 0);
+        assert_eq!(w.cmps.health.get(&a), Some(&5));
+        assert_eq!(w.cmps.name.get(&a), Some(&"A".to_string()));
+        assert_eq!(sim.cmps.health.len(), 1);
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn serialize_fork() {
+        #[derive(ComponentSet, Default, Serialize, Deserialize)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+        }
+        let mut w = WorldStorage::<C, ()>::default();
+        let a = w.spawn();
+        insert!(w, health, a, 5);
+        let fork = w.fork();
+
+        assert_eq!(
+            serde_json::to_string(&w).unwrap(),
+            serde_json::to_string(&fork).unwrap()
+        );
+        let w: WorldStorage<C, ()> =
+            serde_json::from_str(&serde_json::to_string(&fork).unwrap()).unwrap();
+        assert_eq!(w.cmps.health.get(&a), Some(&5));
+    }
+
+    #[test]
+    fn diff_and_patch() {
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+            #[component(no_diff)]
+            pub sprite: ComponentStorage<u32>,
+        }
+        let mut w = WorldStorage::<C, ()>::default();
+        let a = w.spawn();
+        let b = w.spawn();
+        insert!(w, health, a, 5);
+        insert!(w, health, b, 3);
+        insert!(w, sprite, a, 1);
+
+        let mut next = w.fork();
+        next.despawn(a);
+        *next.cmps.health.get_mut(&b).unwrap() = 2;
+        let c = next.spawn();
+        insert!(next, health, c, 1);
+        insert!(next, sprite, c, 1);
+        next.set_parent(c, b);
+
+        let patch = w.diff(&next);
+        assert_eq!(patch.spawned, vec![c]);
+        assert_eq!(patch.despawned, vec![a]);
+        assert_eq!(patch.components.health.changed, vec![(b, 2)]);
+        assert_eq!(patch.parents, vec![(c, Some(b))]);
+
+        w.apply_patch(&patch);
+        assert!(!w.is_valid(&a));
+        assert_eq!(w.cmps.health.get(&a), None);
+        assert_eq!(w.cmps.sprite.get(&a), None);
+        assert_eq!(w.cmps.health.get(&b), Some(&2));
+        assert_eq!(w.cmps.health.get(&c), Some(&1));
+        assert_eq!(w.cmps.sprite.get(&c), None);
+        assert_eq!(w.parent(&c), Some(b));
+        assert_eq!(w.spawn(), next.spawn());
+
+        // the recycle order is kept
+        let mut next = w.fork();
+        let d = next.spawn();
+        next.despawn(c);
+        next.despawn(b);
+        next.despawn(d);
+        w.apply_patch(&w.diff(&next));
+        assert_eq!(w.entities().count(), 1);
+        assert_eq!(w.parent(&c), None);
+        for _ in 0..4 {
+            assert_eq!(w.spawn(), next.spawn());
+        }
+
+        let empty = w.diff(&w);
+        assert!(empty.spawned.is_empty() && empty.despawned.is_empty());
+        assert_eq!(empty.components, CPatch::default());
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn serialize_patch() {
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub name: ComponentStorage<String>,
+        }
+        let mut w = WorldStorage::<C, ()>::default();
+        let mut next = w.fork();
+        let a = next.spawn();
+        insert!(next, name, a, "A".to_string());
+
+        let patch = w.diff(&next);
+        let serialized = serde_json::to_string(&patch).unwrap();
+        let patch: WorldPatch<CPatch> = serde_json::from_str(&serialized).unwrap();
+
+        w.apply_patch(&patch);
+        assert_eq!(w.cmps.name.get(&a), Some(&"A".to_string()));
+    }
+
+    #[test]
+    fn checksum() {
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+            pub name: ComponentStorage<String>,
+            // floats are not `Hash`
+            #[component(no_hash)]
+            pub sprite: ComponentStorage<f32>,
+        }
+        let mut a = WorldStorage::<C, ()>::default();
+        let a0 = a.spawn();
+        let a1 = a.spawn();
+        insert!(a, health, a0, 1);
+        insert!(a, health, a1, 2);
+        insert!(a, name, a1, "B".to_string());
+
+        // same state, reached in a different order
+        let mut b = WorldStorage::<C, ()>::default();
+        let b0 = b.spawn();
+        let b1 = b.spawn();
+        insert!(b, name, b1, "B".to_string());
+        insert!(b, health, b1, 2);
+        insert!(b, health, b0, 1);
+        insert!(b, sprite, b0, 0.5);
+        b.increment_tick();
+        assert_eq!(a.checksum(), b.checksum());
+
+        *b.cmps.health.get_mut(&b1).unwrap() += 1;
+        assert_ne!(a.checksum(), b.checksum());
+        *b.cmps.health.get_mut(&b1).unwrap() -= 1;
+        assert_eq!(a.checksum(), b.checksum());
+
+        insert!(b, name, b0, "A".to_string());
+        assert_ne!(a.checksum(), b.checksum());
+        b.cmps.name.remove(b0);
+        assert_eq!(a.checksum(), b.checksum());
+
+        b.set_parent(b1, b0);
+        assert_ne!(a.checksum(), b.checksum());
+        b.remove_parent(b1);
+        assert_eq!(a.checksum(), b.checksum());
+
+        b.despawn(b0);
+        let _ = b.spawn();
+        assert_ne!(a.checksum(), b.checksum());
+    }
+
+    #[test]
+    fn debug_entity() {
+        struct Handle;
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+            pub name: ComponentStorage<String>,
+            pub player: ComponentStorage<()>,
+            #[component(no_clone, no_hash, no_debug)]
+            pub handle: ComponentStorage<Handle>,
+        }
+        #[derive(Debug, Default)]
+        struct R {
+            level: u32,
+        }
+        let mut w = WorldStorage::<C, R>::default();
+        w.res.level += 1;
+        let a = w.spawn();
+        let b = w.spawn();
+        insert!(w, health, a, 5);
+        insert!(w, name, a, "Rat".to_string());
+        insert!(w, handle, a, Handle);
+        insert!(w, player, b, ());
+
+        assert_eq!(w.cmps.component_names(a), vec!["health", "name", "handle"]);
+        assert_eq!(w.cmps.component_names(b), vec!["player"]);
+        assert_eq!(
+            w.cmps.debug_entity(a),
+            "{ health: 5, name: \"Rat\", handle: <no Debug> }"
+        );
+        assert_eq!(w.cmps.debug_entity(b), "{ player: () }");
+
+        w.despawn(b);
+        assert_eq!(
+            format!("{:?}", w),
+            "WorldStorage { tick: 0, entities: {0:0: { health: 5, name: \"Rat\", handle: <no Debug> }}, \
+            res: R { level: 1 } }"
+        );
+    }
+
+    #[test]
+    fn str_access() {
+        #[derive(ComponentSet, Default)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+            pub player: ComponentStorage<()>,
+        }
+        let mut w = WorldStorage::<C, ()>::default();
+        let a = w.spawn();
+        insert!(w, health, a, 5);
+
+        assert!(w.cmps.has_str("health", a));
+        assert!(!w.cmps.has_str("player", a));
+        assert!(!w.cmps.has_str("mana", a));
+
+        assert!(w.cmps.remove_str("health", a));
+        assert!(!w.cmps.remove_str("health", a));
+        assert!(!w.cmps.remove_str("mana", a));
+        assert_eq!(w.cmps.health.get(&a), None);
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn serialized_str_access() {
+        use serde_json::json;
+
+        struct Likes;
+        impl RelationKind for Likes {
+            const ARITY: Arity = Arity::ManyToMany;
+            const ON_DESPAWN: OnDespawn = OnDespawn::Remove;
+        }
+        #[derive(Clone, Debug, Deserialize, Hash, PartialEq, Serialize)]
+        struct Stats {
+            hp: u32,
+            name: String,
+        }
+        struct Handle;
+        #[derive(ComponentSet, Default)]
+        struct C {
+            #[component(serialize)]
+            pub stats: ComponentStorage<Stats>,
+            #[component(serialize)]
+            pub player: ComponentStorage<()>,
+            #[component(serialize)]
+            pub likes: Relation<Likes>,
+            #[component(no_clone, no_hash, no_debug)]
+            pub handle: ComponentStorage<Handle>,
+        }
+        let mut w = WorldStorage::<C, ()>::default();
+        let a = w.spawn();
+        let b = w.spawn();
+
+        w.cmps
+            .insert_str_serialized("stats", a, &json!({ "hp": 5, "name": "Rat" }))
+            .unwrap();
+        w.cmps
+            .insert_str_serialized("player", a, &json!(null))
+            .unwrap();
+        w.cmps
+            .insert_str_serialized("likes", a, &json!([{ "id": 1, "version": 0 }]))
+            .unwrap();
+        assert_eq!(
+            w.cmps.stats.get(&a),
+            Some(&Stats {
+                hp: 5,
+                name: "Rat".to_string()
+            })
+        );
+        assert!(w.cmps.player.get(&a).is_some());
+        assert_eq!(w.cmps.likes.targets(&a), &[b]);
+
+        assert_eq!(
+            w.cmps.get_str_serialized("stats", a),
+            Ok(Some(json!({ "hp": 5, "name": "Rat" })))
+        );
+        assert_eq!(w.cmps.get_str_serialized("stats", b), Ok(None));
+
+        assert_eq!(
+            w.cmps.get_str_serialized("mana", a),
+            Err(ComponentError::UnknownComponent("mana".to_string()))
+        );
+        insert!(w, handle, a, Handle);
+        assert_eq!(
+            w.cmps.get_str_serialized("handle", a),
+            Err(ComponentError::NotSerializable("handle".to_string()))
+        );
+        assert!(matches!(
+            w.cmps.insert_str_serialized("stats", b, &json!({ "hp": "many" })),
+            Err(ComponentError::InvalidValue { component, .. }) if component == "stats"
+        ));
+        assert!(w.cmps.stats.get(&b).is_none());
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn serialize_hierarchy() {
+        #[derive(ComponentSet, Default, Serialize, Deserialize)]
+        struct C {
+            pub name: ComponentStorage<String>,
+        }
+        let mut w = WorldStorage::<C, ()>::default();
+        let chest = w.spawn();
+        let sword = w.spawn();
+        w.set_parent(sword, chest);
+
+        let serialized = serde_json::to_string(&w).unwrap();
+        let mut w: WorldStorage<C, ()> = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(w.children(&chest), &[sword]);
+
+        w.despawn_recursive(chest);
+        assert!(!w.is_valid(&sword));
+    }
+
     #[cfg(feature = "serialize")]
     #[test]
     fn serialize() {
-        #[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
+        #[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
         struct Position {
             x: u32,
             y: u32,
-        };
+        }
 
         #[derive(ComponentSet, Default, Serialize, Deserialize)]
         struct C {
@@ -89,4 +1284,42 @@ mod tests {
             .globals
             .contains(&"GlobalTwenty".to_string()));
     }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn deserialize_old_format() {
+        #[derive(ComponentSet, Default, Serialize, Deserialize)]
+        struct C {
+            pub health: ComponentStorage<u32>,
+        }
+        // saved before the change detection and the hierarchy were added
+        let serialized = r#"{
+            "entities": {
+                "entities": [{ "id": 0, "version": 0 }, { "id": 1, "version": 0 }],
+                "last_recycled": null,
+                "first_recycled": null
+            },
+            "cmps": {
+                "health": {
+                    "dense": [{ "id": 0, "version": 0 }],
+                    "sparse": [0],
+                    "values": [15]
+                }
+            },
+            "res": null
+        }"#;
+        let mut w: WorldStorage<C, ()> = serde_json::from_str(serialized).unwrap();
+        let a = Entity { id: 1, version: 0 };
+        let b = Entity { id: 0, version: 0 };
+        assert_eq!(w.cmps.health.get(&b), Some(&15));
+        assert!(w.cmps.health.is_added(&b, 0));
+        assert_eq!(w.parent(&b), None);
+
+        w.increment_tick();
+        insert!(w, health, a, 5);
+        assert!(w.cmps.health.is_added(&a, 1));
+        assert!(!w.cmps.health.is_added(&b, 1));
+        assert!(w.set_parent(a, b));
+        assert_eq!(w.spawn(), Entity { id: 2, version: 0 });
+    }
 }
@@ -1,13 +1,78 @@
 use proc_macro::TokenStream;
 use quote::quote;
 
-#[proc_macro_derive(ComponentSet)]
+#[proc_macro_derive(ComponentSet, attributes(component))]
 pub fn component_set_derive(input: TokenStream) -> TokenStream {
     let ast = syn::parse(input).expect("Components Derive: Can't parse derive input!");
     impl_component_set(&ast)
 }
 
+/// Path of the storage items, used by the generated code instead of the
+/// prelude names (which might be missing or shadowed at the call site).
+fn storage_path() -> proc_macro2::TokenStream {
+    quote! { ::wunderkammer::storage }
+}
+
+/// Options set on the `ComponentSet` fields with the `#[component(..)]`
+/// attribute.
+#[derive(Default)]
+struct ComponentOptions {
+    /// The component type implements `MapEntities`
+    map_entities: bool,
+    /// The component type does not implement `Clone`, so it's skipped
+    /// by `clone_entity` and the set can't be forked
+    no_clone: bool,
+    /// The component is excluded from the world diffs
+    /// (the diffed components need `Clone` and `PartialEq`)
+    no_diff: bool,
+    /// The component is excluded from the world checksum
+    /// (the hashed components need `Hash`)
+    no_hash: bool,
+    /// The component type does not implement `Debug`,
+    /// so `debug_entity` shows a placeholder instead of the value
+    no_debug: bool,
+    /// The component is accessible by the serialized string keyed methods
+    /// (needs the serde traits)
+    serialize: bool,
+}
+
+fn component_options(field: &syn::Field) -> ComponentOptions {
+    let mut options = ComponentOptions::default();
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("component"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("map_entities") {
+                options.map_entities = true;
+                Ok(())
+            } else if meta.path.is_ident("no_clone") {
+                options.no_clone = true;
+                Ok(())
+            } else if meta.path.is_ident("no_diff") {
+                options.no_diff = true;
+                Ok(())
+            } else if meta.path.is_ident("no_hash") {
+                options.no_hash = true;
+                Ok(())
+            } else if meta.path.is_ident("no_debug") {
+                options.no_debug = true;
+                Ok(())
+            } else if meta.path.is_ident("serialize") {
+                options.serialize = true;
+                Ok(())
+            } else {
+                Err(meta.error("Components Derive: Unknown component option!"))
+            }
+        })
+        .expect("Components Derive: Can't parse the component attribute!");
+    }
+    options
+}
+
 fn impl_component_set(ast: &syn::DeriveInput) -> TokenStream {
+    let storage = storage_path();
     let name = &ast.ident;
 
     let syn::Data::Struct(data_struct) = &ast.data else {
@@ -15,19 +80,479 @@ fn impl_component_set(ast: &syn::DeriveInput) -> TokenStream {
     };
     let members_despawn = data_struct.fields.members();
     let members_entities = data_struct.fields.members();
+    let members_has = data_struct.fields.members();
+    let members_is = data_struct.fields.members();
+    let members_remove = data_struct.fields.members();
+    let members_tick = data_struct.fields.members();
+    let members_cascade = data_struct.fields.members();
+
+    let members_move = data_struct
+        .fields
+        .members()
+        .zip(data_struct.fields.iter())
+        .map(
+            |(member, field)| match component_options(field).map_entities {
+                true => quote! {
+                    self.#member.__move_entity_mapped(&mut other.#member, entity, mapper);
+                },
+                false => quote! { self.#member.move_entity(&mut other.#member, entity, mapper); },
+            },
+        );
+
+    let members_clone = data_struct
+        .fields
+        .members()
+        .zip(data_struct.fields.iter())
+        .filter(|(_, field)| !component_options(field).no_clone)
+        .map(|(member, _)| member);
+
+    // sets with fields that can't be copied on write are not forkable
+    let fork_definition = match data_struct
+        .fields
+        .iter()
+        .any(|field| component_options(field).no_clone)
+    {
+        true => quote! {},
+        false => {
+            let members_fork = data_struct.fields.members();
+            quote! {
+                impl #storage::ForkComponents for #name {
+                    fn fork(&mut self) -> Self {
+                        Self {
+                            #(#members_fork: self.#members_fork.fork(),)*
+                        }
+                    }
+                }
+            }
+        }
+    };
+
+    // the components have to be cloned to be stored in the patch
+    let diffed = data_struct
+        .fields
+        .members()
+        .zip(data_struct.fields.iter())
+        .filter(|(_, field)| {
+            let options = component_options(field);
+            !options.no_clone && !options.no_diff
+        })
+        .collect::<Vec<_>>();
+    let members_diff = diffed.iter().map(|(member, _)| member).collect::<Vec<_>>();
+    let patch_members = diffed
+        .iter()
+        .enumerate()
+        .map(|(i, (member, _))| match member {
+            syn::Member::Named(_) => member.clone(),
+            syn::Member::Unnamed(_) => syn::Member::Unnamed(syn::Index::from(i)),
+        })
+        .collect::<Vec<_>>();
+    let patch_definition = impl_patch_struct(ast, &diffed);
+
+    let members_hash = data_struct
+        .fields
+        .members()
+        .zip(data_struct.fields.iter())
+        .filter(|(_, field)| !component_options(field).no_hash)
+        .map(|(member, _)| member)
+        .collect::<Vec<_>>();
+    let patch_name = quote::format_ident!("{}Patch", name);
+
+    let members_names = data_struct.fields.members();
+    let members_debug = data_struct
+        .fields
+        .members()
+        .zip(data_struct.fields.iter())
+        .map(|(member, field)| match component_options(field).no_debug {
+            true => quote! {
+                if self.#member.get(&entity).is_some() {
+                    components.push(format!("{}: <no Debug>", stringify!(#member)));
+                }
+            },
+            false => quote! {
+                if let Some(value) = self.#member.get(&entity) {
+                    components.push(format!("{}: {:?}", stringify!(#member), value));
+                }
+            },
+        });
+
+    let serialized_access = impl_serialized_access(data_struct);
+    let members_schema = data_struct
+        .fields
+        .members()
+        .zip(data_struct.fields.iter())
+        .map(|(member, field)| {
+            let ty = &field.ty;
+            match cfg!(feature = "serialize") && component_options(field).serialize {
+                true => quote! { <#ty>::serde_schema(stringify!(#member)) },
+                false => quote! { <#ty>::schema(stringify!(#member)) },
+            }
+        });
 
     let gen = quote! {
-        impl ComponentSet for #name {
-            fn remove_all_components(&mut self, entity: Entity) {
+        #patch_definition
+        #fork_definition
+
+        impl #storage::ComponentSet for #name {
+            type Patch = #patch_name;
+
+            fn schema() -> #storage::Schema {
+                #storage::Schema {
+                    components: vec![#(#members_schema),*],
+                }
+            }
+
+            fn remove_all_components(&mut self, entity: #storage::Entity) {
                 #(self.#members_despawn.remove(entity);)*
             }
 
-            fn entities_str(&self, component: &str) -> Vec<&Entity> {
+            fn entities_str(&self, component: &str) -> Vec<&#storage::Entity> {
                 match component {
-                    #(stringify!(#members_entities) => self.#members_entities.entities().collect(),)*
+                    #(stringify!(#members_entities) => {
+                        self.#members_entities.entities().collect()
+                    })*
                     _ => Vec::new()
                 }
             }
+
+            fn is_component_str(component: &str) -> bool {
+                matches!(component, #(stringify!(#members_is))|*)
+            }
+
+            fn has_str(&self, component: &str, entity: #storage::Entity) -> bool {
+                match component {
+                    #(stringify!(#members_has) => self.#members_has.get(&entity).is_some(),)*
+                    _ => false
+                }
+            }
+
+            fn remove_str(&mut self, component: &str, entity: #storage::Entity) -> bool {
+                match component {
+                    #(stringify!(#members_remove) => {
+                        self.#members_remove.__remove_str(entity)
+                    })*
+                    _ => false
+                }
+            }
+
+            #serialized_access
+
+            fn set_tick(&mut self, tick: #storage::Tick) {
+                #(self.#members_tick.set_tick(tick);)*
+            }
+
+            fn cascade_despawn(&self, entity: &#storage::Entity) -> Vec<#storage::Entity> {
+                let mut cascade = Vec::new();
+                #(cascade.extend(
+                    #storage::CascadeDespawn::cascade_despawn(&self.#members_cascade, entity)
+                );)*
+                cascade
+            }
+
+            fn move_entity(
+                &mut self,
+                other: &mut Self,
+                entity: #storage::Entity,
+                mapper: &mut dyn FnMut(#storage::Entity) -> Option<#storage::Entity>,
+            ) {
+                #(#members_move)*
+            }
+
+            fn clone_entity(&mut self, src: #storage::Entity, dst: #storage::Entity) {
+                #(self.#members_clone.clone_entity(src, dst);)*
+            }
+
+            fn diff(&self, other: &Self) -> Self::Patch {
+                #patch_name {
+                    #(#patch_members: #storage::StorageDiff::diff(
+                        &self.#members_diff,
+                        &other.#members_diff
+                    ),)*
+                }
+            }
+
+            fn apply_patch(&mut self, patch: &Self::Patch) {
+                #(#storage::StorageDiff::apply_patch(
+                    &mut self.#members_diff,
+                    &patch.#patch_members
+                );)*
+            }
+
+            fn component_names(&self, entity: #storage::Entity) -> Vec<&'static str> {
+                let mut names = Vec::new();
+                #(
+                    if self.#members_names.get(&entity).is_some() {
+                        names.push(stringify!(#members_names));
+                    }
+                )*
+                names
+            }
+
+            fn debug_entity(&self, entity: #storage::Entity) -> String {
+                let mut components: Vec<String> = Vec::new();
+                #(#members_debug)*
+                format!("{{ {} }}", components.join(", "))
+            }
+
+            fn hash_components<H: ::std::hash::Hasher>(&self, state: &mut H) {
+                #(
+                    ::std::hash::Hash::hash(stringify!(#members_hash), state);
+                    ::std::hash::Hash::hash(&self.#members_hash, state);
+                )*
+            }
+        }
+    };
+    gen.into()
+}
+
+/// String keyed access to the serialized components.
+/// Generated only with the `serialize` feature.
+fn impl_serialized_access(data_struct: &syn::DataStruct) -> proc_macro2::TokenStream {
+    if !cfg!(feature = "serialize") {
+        return quote! {};
+    }
+    let storage = &storage_path();
+    let arms = |access: fn(&syn::Member, &ComponentOptions) -> proc_macro2::TokenStream| {
+        data_struct
+            .fields
+            .members()
+            .zip(data_struct.fields.iter())
+            .map(move |(member, field)| {
+                let options = component_options(field);
+                if !options.serialize {
+                    return quote! {
+                        stringify!(#member) => {
+                            Err(#storage::ComponentError::NotSerializable(component.to_string()))
+                        }
+                    };
+                }
+                let access = access(&member, &options);
+                quote! {
+                    stringify!(#member) => #access.map_err(|e| {
+                        #storage::ComponentError::InvalidValue {
+                            component: component.to_string(),
+                            message: e.to_string(),
+                        }
+                    }),
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+    let get_arms = arms(|member, _| quote! { self.#member.get_serialized(&entity) });
+    let insert_arms = arms(|member, _| {
+        quote! { self.#member.__insert_serialized(entity, value).map(|_| ()) }
+    });
+    // relations map their targets, other storages only the `map_entities` values
+    let insert_mapped_arms = arms(|member, options| match options.map_entities {
+        true => {
+            let storage = storage_path();
+            quote! {
+                self.#member
+                    .__insert_serialized_with(entity, value, |v| {
+                        #storage::MapEntities::map_entities(v, mapper)
+                    })
+                    .map(|_| ())
+            }
+        }
+        false => quote! {
+            self.#member.__insert_serialized_targets(entity, value, mapper).map(|_| ())
+        },
+    });
+
+    quote! {
+        fn get_str_serialized(
+            &self,
+            component: &str,
+            entity: #storage::Entity,
+        ) -> Result<Option<#storage::JsonValue>, #storage::ComponentError> {
+            match component {
+                #(#get_arms)*
+                _ => Err(#storage::ComponentError::UnknownComponent(component.to_string())),
+            }
+        }
+
+        fn insert_str_serialized(
+            &mut self,
+            component: &str,
+            entity: #storage::Entity,
+            value: &#storage::JsonValue,
+        ) -> Result<(), #storage::ComponentError> {
+            match component {
+                #(#insert_arms)*
+                _ => Err(#storage::ComponentError::UnknownComponent(component.to_string())),
+            }
+        }
+
+        fn insert_str_serialized_mapped(
+            &mut self,
+            component: &str,
+            entity: #storage::Entity,
+            value: &#storage::JsonValue,
+            mapper: &mut dyn FnMut(#storage::Entity) -> #storage::Entity,
+        ) -> Result<(), #storage::ComponentError> {
+            match component {
+                #(#insert_mapped_arms)*
+                _ => Err(#storage::ComponentError::UnknownComponent(component.to_string())),
+            }
+        }
+    }
+}
+
+/// Patch struct holding the changes of every diffed field.
+/// The patch types are set through generic parameter defaults, so the derived
+/// traits are only implemented if all the patches support them.
+fn impl_patch_struct(
+    ast: &syn::DeriveInput,
+    diffed: &[(syn::Member, &syn::Field)],
+) -> proc_macro2::TokenStream {
+    let storage = storage_path();
+    let vis = &ast.vis;
+    let name = quote::format_ident!("{}Patch", ast.ident);
+    let params = (0..diffed.len())
+        .map(|i| quote::format_ident!("P{}", i))
+        .collect::<Vec<_>>();
+    let types = diffed.iter().map(|(_, field)| &field.ty);
+    let serde_derive = match cfg!(feature = "serialize") {
+        true => quote! {
+            #[derive(::wunderkammer::__serde::Serialize, ::wunderkammer::__serde::Deserialize)]
+            #[serde(crate = "::wunderkammer::__serde")]
+        },
+        false => quote! {},
+    };
+
+    let fields = match &ast.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(_),
+            ..
+        }) => {
+            let idents = diffed.iter().map(|(member, _)| member);
+            quote! { { #(pub #idents: #params,)* } }
+        }
+        _ => quote! { ( #(pub #params,)* ); },
+    };
+
+    quote! {
+        #[derive(Clone, Debug, Default, PartialEq)]
+        #serde_derive
+        #vis struct #name<#(#params = <#types as #storage::StorageDiff>::Patch),*> #fields
+    }
+}
+
+#[proc_macro_derive(Bundle, attributes(bundle))]
+pub fn bundle_derive(input: TokenStream) -> TokenStream {
+    let ast = syn::parse(input).expect("Bundle Derive: Can't parse derive input!");
+    impl_bundle(&ast)
+}
+
+fn impl_bundle(ast: &syn::DeriveInput) -> TokenStream {
+    let storage = storage_path();
+    let name = &ast.ident;
+
+    let components: syn::Path = ast
+        .attrs
+        .iter()
+        .find(|a| a.path().is_ident("bundle"))
+        .expect("Bundle Derive: Missing #[bundle(ComponentSetType)] attribute!")
+        .parse_args()
+        .expect("Bundle Derive: Can't parse the ComponentSet type!");
+
+    let syn::Data::Struct(data_struct) = &ast.data else {
+        panic!("Bundle Derive: Not a data struct!")
+    };
+    let syn::Fields::Named(fields) = &data_struct.fields else {
+        panic!("Bundle Derive: Fields have to be named after the components!")
+    };
+    let members = fields
+        .named
+        .iter()
+        .map(|f| f.ident.as_ref().unwrap())
+        .collect::<Vec<_>>();
+
+    let gen = quote! {
+        impl #storage::Bundle<#components> for #name {
+            fn insert_into(self, cmps: &mut #components, entity: #storage::Entity) {
+                #(cmps.#members.__insert(entity, self.#members);)*
+            }
+
+            fn remove_from(cmps: &mut #components, entity: #storage::Entity) -> Option<Self> {
+                if !(true #(&& cmps.#members.get(&entity).is_some())*) {
+                    return None;
+                }
+                Some(Self {
+                    #(#members: cmps.#members.remove(entity)?,)*
+                })
+            }
+        }
+    };
+    gen.into()
+}
+
+#[proc_macro_derive(MapEntities, attributes(entities))]
+pub fn map_entities_derive(input: TokenStream) -> TokenStream {
+    let ast = syn::parse(input).expect("MapEntities Derive: Can't parse derive input!");
+    impl_map_entities(&ast)
+}
+
+fn is_entities_field(field: &syn::Field) -> bool {
+    field.attrs.iter().any(|a| a.path().is_ident("entities"))
+}
+
+fn impl_map_entities(ast: &syn::DeriveInput) -> TokenStream {
+    let storage = storage_path();
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    let body = match &ast.data {
+        syn::Data::Struct(data_struct) => {
+            let members = data_struct
+                .fields
+                .members()
+                .zip(data_struct.fields.iter())
+                .filter(|(_, field)| is_entities_field(field))
+                .map(|(member, _)| member);
+            quote! {
+                #(#storage::MapEntities::map_entities(&mut self.#members, mapper);)*
+            }
+        }
+        syn::Data::Enum(data_enum) => {
+            let arms = data_enum.variants.iter().map(|variant| {
+                let ident = &variant.ident;
+                let bindings = variant
+                    .fields
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, field)| is_entities_field(field))
+                    .map(|(i, _)| quote::format_ident!("field_{}", i))
+                    .collect::<Vec<_>>();
+                let members = variant
+                    .fields
+                    .members()
+                    .zip(variant.fields.iter())
+                    .filter(|(_, field)| is_entities_field(field))
+                    .map(|(member, _)| member);
+                quote! {
+                    Self::#ident { #(#members: #bindings,)* .. } => {
+                        #(#storage::MapEntities::map_entities(#bindings, mapper);)*
+                    }
+                }
+            });
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        syn::Data::Union(_) => panic!("MapEntities Derive: Unions are not supported!"),
+    };
+
+    let gen = quote! {
+        impl #impl_generics #storage::MapEntities for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn map_entities(
+                &mut self,
+                mapper: &mut dyn FnMut(#storage::Entity) -> #storage::Entity,
+            ) {
+                #body
+            }
         }
     };
     gen.into()